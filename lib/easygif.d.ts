@@ -3,7 +3,8 @@
  */
 export class Probe {
   /**
-   * Get the user-specified src of the file resolved
+   * Get the user-specified src of the file resolved, or a `buffer:<n>` or
+   * `stream:<n>` identifier for data probed from memory or from a stream
    *
    * Wrapper over a native property. Enumerable, not displayed on default
    * inspect
@@ -36,7 +37,8 @@ export class Probe {
   public get height(): number;
 
   /**
-   * Get the FPS (frames per second) of the file resolved
+   * Get the FPS (frames per second) of the file resolved. For streams, as
+   * per the frames received so far
    *
    * Wrapper over a native property. Enumerable, not displayed on default
    * inspect
@@ -45,20 +47,261 @@ export class Probe {
    * @throws It just throws. You better catch the errors
    */
   public get fps(): number;
+
+  /**
+   * Get the number of frames of the file resolved. For streams, the frames
+   * received whole so far
+   *
+   * Wrapper over a native property. Enumerable, not displayed on default
+   * inspect
+   *
+   * @returns The frame count
+   * @throws It just throws. You better catch the errors
+   */
+  public get frameCount(): number;
+
+  /**
+   * Get the total duration of the file resolved. Sum of the delays as
+   * stored in the file. For streams, as per the frames received so far
+   *
+   * Wrapper over a native property. Enumerable, not displayed on default
+   * inspect
+   *
+   * @returns The duration (ms)
+   * @throws It just throws. You better catch the errors
+   */
+  public get duration(): number;
+
+  /**
+   * Get the per-frame delays of the file resolved, as stored in the Graphic
+   * Control Extensions. Note that players usually treat delays below `2cs`
+   * as `10cs`. For streams, the frames received whole so far
+   *
+   * Wrapper over a native property. Enumerable, not displayed on default
+   * inspect
+   *
+   * @returns The delay of every frame
+   * @throws It just throws. You better catch the errors
+   */
+  public get delays(): FrameDelay[];
+}
+
+/**
+ * Delay of a single GIF frame
+ */
+export interface FrameDelay {
+  /**
+   * The delay (cs)
+   */
+  cs: number;
+  /**
+   * The delay (ms)
+   */
+  ms: number;
+}
+
+/**
+ * Structure of a frame as stored in the file
+ */
+export interface FrameMetadata {
+  /**
+   * The frame index, counting every frame of the file
+   */
+  index: number;
+  /**
+   * When the frame is displayed (ms), as per the delays stored in the file
+   */
+  timestampMs: number;
+  /**
+   * How long the frame is displayed (ms)
+   */
+  delayMs: number;
+  /**
+   * What happens to the frame area once the frame is displayed
+   */
+  disposal: Disposal;
+  /**
+   * The area the frame image covers on the canvas, in the file dimensions
+   * regardless of `scale`
+   */
+  rect: FrameRect;
+  /**
+   * The color table index marking the transparent pixels, if any
+   */
+  transparentIndex: number | null;
+  /**
+   * Whether the frame has its own color table
+   */
+  localPalette: boolean;
 }
 
 /**
- * Abstraction for iterating over GIF frames
+ * A frame yielded with `ExtractOptions`.`metadata` set
  */
-export class Extract {
+export interface ExtractFrame extends FrameMetadata {
+  /**
+   * The composited frame in the `pixelFormat` format
+   */
+  pixels: Buffer;
+}
+
+/**
+ * An image yielded with `ExtractOptions`.`raw` set, as stored in the file
+ */
+export interface ExtractRawFrame extends FrameMetadata {
+  /**
+   * Whether the image rows are stored interlaced. `indices` are in
+   * row-major order either way
+   */
+  interlaced: boolean;
+  /**
+   * The local color table, or the global one, as RGB triples. `null` if
+   * the file has neither
+   */
+  palette: Buffer | null;
+  /**
+   * The color table index of each pixel of `rect`, one byte per pixel
+   */
+  indices: Buffer;
+}
+
+/**
+ * Area of a frame image on the canvas
+ */
+export interface FrameRect {
+  /**
+   * The left offset (px)
+   */
+  left: number;
+  /**
+   * The top offset (px)
+   */
+  top: number;
+  /**
+   * The width (px)
+   */
+  width: number;
+  /**
+   * The height (px)
+   */
+  height: number;
+}
+
+/**
+ * Disposal method of a frame
+ *
+ * - `unspecified` Left to the decoder, treated as `keep`
+ * - `keep` The frame is left in place
+ * - `background` The frame area is cleared to transparent
+ * - `previous` The frame area is restored to what it was before
+ */
+export type Disposal = "unspecified" | "keep" | "background" | "previous";
+
+/**
+ * Abstraction for iterating over GIF frames. `T` is `ExtractFrame` with
+ * `ExtractOptions`.`metadata` set, `ExtractRawFrame` with
+ * `ExtractOptions`.`raw` set
+ */
+export class Extract<
+  T extends Buffer | ExtractFrame | ExtractRawFrame = Buffer,
+> {
+  /**
+   * Get the width of the frames yielded, as per the `scale` option
+   *
+   * Wrapper over a native property. Enumerable, not displayed on default
+   * inspect
+   *
+   * @returns The width (px)
+   * @throws It just throws. You better catch the errors
+   */
+  public get width(): number;
+
+  /**
+   * Get the height of the frames yielded, as per the `scale` option
+   *
+   * Wrapper over a native property. Enumerable, not displayed on default
+   * inspect
+   *
+   * @returns The height (px)
+   * @throws It just throws. You better catch the errors
+   */
+  public get height(): number;
+
+  /**
+   * Get the palette the frames are indexed into with the `indexed` pixel
+   * format, the global color table of the file. It can be passed as is to
+   * `CombineOptions`.`palette`
+   *
+   * Wrapper over a native property. Enumerable, not displayed on default
+   * inspect
+   *
+   * @returns The palette, or `null` with other pixel formats
+   * @throws It just throws. You better catch the errors
+   */
+  public get palette(): PaletteEntry[] | null;
+
   /**
    * Iterable over the GIF frames. Each call does a read into preallocated
    * buffer
    *
-   * @yields GIF frame in RGBA format
+   * Streamed input can only be iterated this way once it is fully
+   * received, as waiting for chunks would block the event loop they are
+   * received on
+   *
+   * @yields GIF frame in the `pixelFormat` format, as per `T`
+   * @throws It just throws. You better catch the errors
+   */
+  public [Symbol.iterator](): Iterator<T>;
+
+  /**
+   * Asynchronous iterable over the GIF frames. Each frame is read off the
+   * main thread, so large GIFs do not block the event loop
+   *
+   * Only one read may be in progress at a time, so await each frame before
+   * requesting the next one
+   *
+   * Streamed input yields each frame as soon as it is received. A read
+   * waiting for chunks holds a thread of the libuv threadpool
+   *
+   * @yields GIF frame in the `pixelFormat` format, as per `T`
+   * @throws It just throws. You better catch the errors
+   */
+  public [Symbol.asyncIterator](): AsyncIterator<T>;
+
+  /**
+   * Moves the iteration on to the frame at `index`, counting every frame
+   * of the file. Iterating then goes on with the selected frames from it
+   *
+   * Decoding resumes from the nearest keyframe (a frame covering the whole
+   * canvas) or composited state cached ahead of the frame, collected while
+   * the frames are read for the first time. Not supported by the `ffmpeg`
+   * backend
+   *
+   * @param index Frame index
+   * @throws It just throws. You better catch the errors
+   */
+  public seek(index: number): Promise<void>;
+
+  /**
+   * Reads the frame at `index`, selected or not. Same as `seek(index)`
+   * followed by a read, so iterating goes on after it
+   *
+   * @param index Frame index
+   * @returns GIF frame in the `pixelFormat` format, as per `T`
    * @throws It just throws. You better catch the errors
    */
-  public [Symbol.iterator](): Iterator<Buffer>;
+  public frameAt(index: number): Promise<T>;
+
+  /**
+   * Reads the next selected frame straight into `buf`, without allocating
+   * a `Buffer` per frame. Iterating goes on after it
+   *
+   * @param buf Buffer of size `width * height * bytesPerPixel`, as per
+   * `pixelFormat`. Left untouched once there are no frames left
+   * @returns `false` once there are no frames left
+   * @throws It just throws. You better catch the errors
+   */
+  public readInto(buf: Buffer): Promise<boolean>;
 }
 
 /**
@@ -69,7 +312,7 @@ export class Combine {
    * Allocates a buffer of required size, so you don't have to deal with it.
    *
    * Under the hood it basically allocates a `Buffer` of size
-   * `probe.width * probe.height * 4`
+   * `probe.width * probe.height * bytesPerPixel`, as per `pixelFormat`
    *
    * - This method is highly recommended to use so you don't get stuck once
    * you allocate a `Buffer` with invalid size
@@ -84,40 +327,207 @@ export class Combine {
   /**
    * Write a _generated_ frame to the GIF
    *
-   * @param buf Pixel buffer. The format is RGBA
+   * The frame is copied and encoded off the main thread, so `buf` can be
+   * reused right away. The returned promise resolves once the encoder has
+   * room for the frame, so awaiting it keeps the memory usage bounded when
+   * frames are produced faster than they are encoded
+   *
+   * @param buf Pixel buffer, in the `pixelFormat` format
+   * @param options Per-frame parameters
    *
    * @throws It just throws. You better catch the errors
    */
-  public write(buf: Buffer): void;
+  public write(buf: Buffer, options?: WriteOptions | null): Promise<void>;
 
   /**
    * Closes the write stream and reads the end result as a Buffer
    *
-   * @returns Compiled GIF image
+   * With `stream` set, resolves once the last chunk is encoded. Keep
+   * reading the output meanwhile, as the encoder waits for the reader
+   * whenever 1MiB of output is pending
+   *
+   * Rejects unless at least one frame was written
+   *
+   * @returns Compiled GIF image, or its summary if streamed or written to
+   * the `output` file, or the settings fitting `maxBytes` if set
    * @throws It just throws. You better catch the errors
    */
-  public finish(): Promise<Buffer>;
+  public finish(): Promise<Buffer | CombineSummary | CombineFit>;
+
+  /**
+   * Asynchronous iterable over the encoded GIF chunks as they are
+   * produced. Only available with `stream` set. Wrap it with
+   * `Readable.from` to get a readable stream
+   *
+   * Only one read may be in progress at a time, so await each chunk before
+   * requesting the next one
+   *
+   * @yields Chunk of the encoded GIF
+   * @throws It just throws. You better catch the errors
+   */
+  public [Symbol.asyncIterator](): AsyncIterator<Buffer>;
 }
 
 /**
  * Resolve GIF metadata
  *
- * @param src Path to the GIF file. **Relative to the `CWD`**
+ * @param src Path to the GIF file. **Relative to the `CWD`**. Or the GIF
+ * data itself, copied so the `Buffer` can be reused right away. Or a
+ * `Readable`, or any async iterable of `Buffer`, `TypedArray` or `DataView`
+ * chunks, pulled as soon as probed. `extract` then reads the frames from
+ * memory, piping them through stdin for the `ffmpeg` backend
+ *
+ * Streams resolve as soon as the header is received, the per-frame fields
+ * being filled in as the chunks arrive. Every chunk is kept until the
+ * `Probe` and its `Extract` instances are collected, so seeking back works
  *
  * @returns Resulting metadata
  * @throws It just throws. You better catch the errors
  */
-export function probe(src: string): Promise<Probe>;
+export function probe(
+  src: string | Buffer | AsyncIterable<ArrayBufferView>,
+): Promise<Probe>;
+
+/**
+ * Implementation used to decode or encode GIF frames
+ *
+ * - `native` Built-in GIF codec. Does not require `ffmpeg`
+ * - `ffmpeg` Spawns the `ffmpeg` binary
+ */
+export type Backend = "native" | "ffmpeg";
+
+/**
+ * Parameter descriptor for the `extract` function
+ */
+export interface ExtractOptions {
+  /**
+   * The implementation to decode frames with. Defaults to `native`
+   */
+  backend?: Backend | null;
+  /**
+   * The first frame to extract, as an index or a timestamp per `unit`.
+   * Defaults to `0`
+   */
+  start?: number | null;
+  /**
+   * Where to stop extracting (exclusive), as an index or a timestamp per
+   * `unit`. Defaults to the end of the animation
+   */
+  end?: number | null;
+  /**
+   * Extract every `step`-th frame, or the frame displayed every `step`
+   * milliseconds with the `ms` unit, each frame at most once. Defaults to
+   * every frame
+   */
+  step?: number | null;
+  /**
+   * What `start`, `end` and `step` count. Defaults to `index`. Ranges in
+   * `ms` are not supported for streamed input, as they need every delay
+   */
+  unit?: RangeUnit | null;
+  /**
+   * The size to scale the frames to, fit as per `scaleMode`. The actual
+   * size is exposed as `Extract.width` and `Extract.height`
+   */
+  scale?: [number, number] | null;
+  /**
+   * The resampling filter used with `scale`. Defaults to `lanczos`
+   */
+  filter?: Filter | null;
+  /**
+   * How the frames are fit into `scale`. Defaults to `stretch`
+   */
+  scaleMode?: ScaleMode | null;
+  /**
+   * The format of the frames yielded. `indexed` frames refer to
+   * `Extract.palette`, the global color table of the file, and are not
+   * supported by the `ffmpeg` backend. Defaults to `rgba`
+   */
+  pixelFormat?: PixelFormat | null;
+  /**
+   * Yield the frames as views over the internal frame buffer instead of
+   * copies. Each view is overwritten by the next read, so consume or copy
+   * it before reading on. Defaults to `false`
+   */
+  zeroCopy?: boolean | null;
+  /**
+   * Yield `ExtractFrame` objects holding the frame structure along with
+   * the pixels. Not supported by the `ffmpeg` backend. Defaults to `false`
+   */
+  metadata?: boolean | null;
+  /**
+   * Yield `ExtractRawFrame` objects holding each image as stored in the
+   * file, without compositing or disposing it. Cannot be combined with
+   * `scale`, `pixelFormat` or `zeroCopy`, nor `Extract.readInto`. Not
+   * supported by the `ffmpeg` backend. Defaults to `false`
+   */
+  raw?: boolean | null;
+}
+
+/**
+ * Layout of a frame buffer
+ *
+ * - `rgba` 4 bytes per pixel
+ * - `bgra` 4 bytes per pixel
+ * - `rgb24` 3 bytes per pixel, fully opaque
+ * - `gray8` 1 byte of luma per pixel, fully opaque
+ * - `indexed` 1 byte per pixel, an index into a palette. Transparent pixels
+ * use the palette entry with zero alpha
+ */
+export type PixelFormat = "rgba" | "bgra" | "rgb24" | "gray8" | "indexed";
+
+/**
+ * Resampling filter
+ *
+ * - `lanczos` Sharpest, the same filter `CombineOptions`.`scale` uses
+ * - `bilinear` Smoother and faster
+ * - `nearest` Keeps hard pixel edges, the fastest
+ */
+export type Filter = "lanczos" | "bilinear" | "nearest";
+
+/**
+ * How frames are fit into the `scale` size
+ *
+ * - `stretch` Exactly the size, ignoring the aspect ratio
+ * - `fit` Within the size, preserving the aspect ratio
+ * - `fill` Exactly the size, preserving the aspect ratio by cropping the
+ * overflow around the center
+ */
+export type ScaleMode = "stretch" | "fit" | "fill";
+
+/**
+ * Unit of the frame range of the `extract` function
+ *
+ * - `index` Frame indices
+ * - `ms` Milliseconds, as per the delays stored in the file
+ *
+ * Frames before the range are still decoded, as the later ones are
+ * composited upon them, but they are not copied. Decoding stops at the end
+ * of the range
+ */
+export type RangeUnit = "index" | "ms";
 
 /**
  * Create GIF frame extractor instance
  *
  * @param probe Previously resolved GIF metadata
+ * @param options Parameters to rely on
  *
  * @returns Instance for resolving frames
  * @throws It just throws. You better catch the errors
  */
-export function extract(probe: Probe): Promise<Extract>;
+export function extract(
+  probe: Probe,
+  options: ExtractOptions & { raw: true },
+): Promise<Extract<ExtractRawFrame>>;
+export function extract(
+  probe: Probe,
+  options: ExtractOptions & { metadata: true },
+): Promise<Extract<ExtractFrame>>;
+export function extract(
+  probe: Probe,
+  options?: ExtractOptions | null,
+): Promise<Extract>;
 
 /**
  * An optional parameter while combining a GIF
@@ -134,22 +544,219 @@ export enum Repeat {
  */
 export interface CombineOptions {
   /**
-   * The width of the input buffer
+   * The width of the input buffer. At least `1`
    */
   width: number;
   /**
-   * The height of the input buffer
+   * The height of the input buffer. At least `1`
    */
   height: number;
   /**
-   * The frame rate of the output
+   * The frame rate of the output. Frames written without a `delayMs` are
+   * displayed for `1000 / fps` milliseconds
    */
   fps: number;
   /**
-   * The scale for the output
+   * The scale for the output. Both sides are at least `1`
    */
   scale?: [number, number] | null;
   repeat?: Repeat | number | null;
+  /**
+   * The implementation to encode the GIF with. Defaults to `native`
+   */
+  backend?: Backend | null;
+  /**
+   * Expose the encoded GIF incrementally through `Combine` being async
+   * iterable instead of buffering it for `finish()`. Frames are encoded as
+   * they are written, which requires either the `local` palette mode or a
+   * fixed `palette`
+   */
+  stream?: boolean | null;
+  /**
+   * Path to write the encoded GIF to instead of buffering it for
+   * `finish()`. **Relative to the `CWD`**. An existing file is replaced.
+   * Cannot be combined with `stream`
+   */
+  output?: string | null;
+  /**
+   * The maximum number of palette colors, between `2` and `256`. Defaults
+   * to `32`
+   */
+  maxColors?: number | null;
+  /**
+   * The dithering applied while mapping frames onto the palette. Defaults
+   * to `bayer`
+   */
+  dither?: Dither | null;
+  /**
+   * The scale of the `bayer` dither, between `0` and `5`. Lower scales
+   * produce a more visible pattern. Defaults to `2`
+   */
+  bayerScale?: number | null;
+  /**
+   * The pixels counted while generating the global palette. Defaults to
+   * `full`
+   */
+  statsMode?: StatsMode | null;
+  /**
+   * How color tables are assigned to the frames. Defaults to `global`, or
+   * to `local` when `stream` is set without a `palette`
+   */
+  paletteMode?: PaletteMode | null;
+  /**
+   * The mean color error (RGB distance) a frame may have on the global
+   * palette before the `hybrid` palette mode gives it a local table.
+   * Defaults to `8`
+   */
+  paletteThreshold?: number | null;
+  /**
+   * Colors used verbatim as the global palette, either as `[r, g, b]` or
+   * `[r, g, b, a]` entries or as a `Buffer` of RGB triples. Up to 256
+   * colors. Frames are mapped onto it using the configured `dither`, so
+   * `maxColors` and `statsMode` do not apply. Requires the `global` palette
+   * mode, and allows `stream` with it
+   *
+   * The first entry with zero alpha is used for transparent pixels.
+   * Without one, an extra entry is reserved for them as long as the
+   * palette has less than 256 colors
+   *
+   * Not supported by the `ffmpeg` backend
+   */
+  palette?: PaletteEntry[] | Buffer | null;
+  /**
+   * Pixels with alpha under the threshold become transparent, using a
+   * palette index reserved for them. `0` encodes every pixel as opaque.
+   * Defaults to `128`
+   */
+  alphaThreshold?: number | null;
+  /**
+   * Crop every frame to the area changed since the previous one, encoding
+   * the unchanged pixels within it as transparent. The disposal methods are
+   * picked accordingly. Reserves a palette entry for the unchanged pixels.
+   * Defaults to `true`
+   */
+  optimize?: boolean | null;
+  /**
+   * The maximum size of the encoded GIF (bytes). `finish()` encodes the
+   * written frames over again, reducing the colors down to `16`, then the
+   * size down to a half, then keeping down to every 4th frame and finally
+   * reducing the size down to `16px`, until the output fits. Frames are
+   * kept in memory until then. Cannot be combined with `stream`
+   *
+   * Not supported by the `ffmpeg` backend
+   */
+  maxBytes?: number | null;
+  /**
+   * Lossy LZW compression level, between `0` and `255`, akin to the
+   * `gifsicle` `--lossy` option. The compressor may replace a pixel with a
+   * palette color within this RGB distance when that extends a repeated
+   * run, producing smaller files at the cost of noise. Transparent pixels
+   * are kept exact. `0` disables it. Defaults to `0`
+   *
+   * Not supported by the `ffmpeg` backend
+   */
+  lossy?: number | null;
+  /**
+   * The format of the frames written. `indexed` frames refer to a fixed
+   * `palette`, which is then required, and are not supported by the
+   * `ffmpeg` backend. Defaults to `rgba`
+   */
+  pixelFormat?: PixelFormat | null;
+}
+
+/**
+ * A single palette color. Only zero alpha is meaningful, marking the
+ * transparent entry
+ */
+export type PaletteEntry =
+  | [r: number, g: number, b: number]
+  | [r: number, g: number, b: number, a: number];
+
+/**
+ * Palette mode
+ *
+ * - `global` A single palette generated from every frame. Frames are
+ * buffered until `finish()`
+ * - `local` A palette generated from each frame, encoded as it is written
+ * - `hybrid` The global palette, with a local one for the frames it does
+ * not represent well, see `paletteThreshold`. Not supported by the
+ * `ffmpeg` backend
+ */
+export type PaletteMode = "global" | "local" | "hybrid";
+
+/**
+ * Dithering algorithm
+ *
+ * - `none` Maps every pixel onto the nearest palette color
+ * - `bayer` Ordered dithering, see `bayerScale`
+ * - `floyd_steinberg` Floyd–Steinberg error diffusion
+ * - `sierra` Sierra Lite error diffusion (`sierra2_4a` in `ffmpeg`)
+ */
+export type Dither = "none" | "bayer" | "floyd_steinberg" | "sierra";
+
+/**
+ * Palette statistics mode
+ *
+ * - `full` Counts every pixel of every frame
+ * - `diff` Counts only the pixels that differ from the previous frame,
+ * favoring the moving parts over a static background
+ */
+export type StatsMode = "full" | "diff";
+
+/**
+ * Summary resolved by `Combine.finish()` once a streamed or file output
+ * ends
+ */
+export interface CombineSummary {
+  /**
+   * The size of the encoded GIF (bytes)
+   */
+  bytes: number;
+  /**
+   * The `output` file path, if set
+   */
+  path?: string;
+}
+
+/**
+ * Result resolved by `Combine.finish()` with `maxBytes` set, along with the
+ * settings the output fits with
+ */
+export interface CombineFit extends CombineSummary {
+  /**
+   * Compiled GIF image, unless written to the `output` file
+   */
+  buffer?: Buffer;
+  /**
+   * The output width (px)
+   */
+  width: number;
+  /**
+   * The output height (px)
+   */
+  height: number;
+  /**
+   * The maximum number of palette colors used
+   */
+  maxColors: number;
+  /**
+   * Every `frameStep`-th frame is kept, displayed for the duration of the
+   * frames dropped after it
+   */
+  frameStep: number;
+}
+
+/**
+ * Per-frame parameters for the `Combine.write` method
+ */
+export interface WriteOptions {
+  /**
+   * How long the frame is displayed (ms). Defaults to `1000 / fps`.
+   * GIF delays are stored in centiseconds, so the value is rounded
+   *
+   * Not supported by the `ffmpeg` backend
+   */
+  delayMs?: number | null;
 }
 
 /**
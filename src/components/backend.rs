@@ -0,0 +1,23 @@
+use std::borrow::Cow;
+
+use crate::{
+  napi::native::{self, FromNapi},
+  Result,
+};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Backend {
+  #[default]
+  Native,
+  Ffmpeg,
+}
+
+impl FromNapi for Backend {
+  fn from_napi(env: native::env, v: native::value) -> Result<Self> {
+    match String::from_napi(env, v)?.as_str() {
+      "native" => Ok(Self::Native),
+      "ffmpeg" => Ok(Self::Ffmpeg),
+      v => Err(Cow::Owned(format!("Unknown backend {v:?}"))),
+    }
+  }
+}
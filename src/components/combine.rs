@@ -1,18 +1,26 @@
 use std::{
   alloc::{dealloc, Layout},
   borrow::Cow,
+  collections::VecDeque,
   ffi::c_void,
-  io::{Read, Write},
-  process::{ChildStdin, ChildStdout, Command, Stdio},
+  io::{self, Write},
+  process::{Child, ChildStdin, Command, Stdio},
   ptr::{addr_of_mut, drop_in_place, null_mut},
+  sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+  thread::{self, JoinHandle},
 };
 
 use crate::{
+  gif,
   napi::native::{self, FromNapi, ToNapi},
   Result,
 };
 
-#[derive(Default, Debug)]
+use super::{
+  Backend, Chunks, Fitting, Output, PaletteMode, PixelFormat, Sink, StatsMode, UserPalette,
+};
+
+#[derive(Default, Debug, Clone)]
 #[repr(u8)]
 pub(crate) enum Repeat {
   #[default]
@@ -46,13 +54,26 @@ impl ToNapi for Repeat {
   }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub(crate) struct CombineOptions {
   pub(crate) width: u32,
   pub(crate) height: u32,
   pub(crate) fps: f64,
   pub(crate) scale: Option<(u32, u32)>,
   pub(crate) repeat: Repeat,
+  pub(crate) backend: Backend,
+  pub(crate) stream: bool,
+  pub(crate) output: Option<String>,
+  pub(crate) max_colors: u16,
+  pub(crate) dither: gif::Dither,
+  pub(crate) stats: StatsMode,
+  pub(crate) palette_mode: PaletteMode,
+  pub(crate) palette: Option<UserPalette>,
+  pub(crate) alpha_threshold: u8,
+  pub(crate) optimize: bool,
+  pub(crate) max_bytes: Option<u64>,
+  pub(crate) lossy: u8,
+  pub(crate) pixel_format: PixelFormat,
 }
 
 impl FromNapi for CombineOptions {
@@ -62,6 +83,12 @@ impl FromNapi for CombineOptions {
         .and_then(|v| native::get_value_uint32(env, v))?;
       let height = native::get_named_property(env, v, "height")
         .and_then(|v| native::get_value_uint32(env, v))?;
+      if width == 0 {
+        return Err(Cow::Borrowed("Invalid `CombineOptions`.`width` property"));
+      }
+      if height == 0 {
+        return Err(Cow::Borrowed("Invalid `CombineOptions`.`height` property"));
+      }
       let fps =
         native::get_named_property(env, v, "fps").and_then(|v| native::get_value_double(env, v))?;
 
@@ -80,6 +107,9 @@ impl FromNapi for CombineOptions {
         native::valuetype::Null | native::valuetype::Undefined => None,
         _ => return Err(Cow::Borrowed("Invalid `CombineOptions`.`scale` property")),
       };
+      if scale.is_some_and(|(w, h)| w == 0 || h == 0) {
+        return Err(Cow::Borrowed("Invalid `CombineOptions`.`scale` property"));
+      }
 
       let vrepeat = native::get_named_property(env, v, "repeat")?;
       let repeat = match native::value_type(env, vrepeat)? {
@@ -88,12 +118,246 @@ impl FromNapi for CombineOptions {
         _ => return Err(Cow::Borrowed("Invalid `CombineOptions`.`repeat` property")),
       };
 
+      let vbackend = native::get_named_property(env, v, "backend")?;
+      let backend = match native::value_type(env, vbackend)? {
+        native::valuetype::String => Backend::from_napi(env, vbackend)?,
+        native::valuetype::Null | native::valuetype::Undefined => Backend::default(),
+        _ => return Err(Cow::Borrowed("Invalid `CombineOptions`.`backend` property")),
+      };
+
+      let vstream = native::get_named_property(env, v, "stream")?;
+      let stream = match native::value_type(env, vstream)? {
+        native::valuetype::Boolean => native::get_value_bool(env, vstream)?,
+        native::valuetype::Null | native::valuetype::Undefined => false,
+        _ => return Err(Cow::Borrowed("Invalid `CombineOptions`.`stream` property")),
+      };
+
+      let voutput = native::get_named_property(env, v, "output")?;
+      let output = match native::value_type(env, voutput)? {
+        native::valuetype::String => Some(native::get_value_string(env, voutput)?),
+        native::valuetype::Null | native::valuetype::Undefined => None,
+        _ => return Err(Cow::Borrowed("Invalid `CombineOptions`.`output` property")),
+      };
+      let vmax_colors = native::get_named_property(env, v, "maxColors")?;
+      let max_colors = match native::value_type(env, vmax_colors)? {
+        native::valuetype::Number => match native::get_value_double(env, vmax_colors)? {
+          #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+          v if v.fract() == 0_f64 && (2_f64..=256_f64).contains(&v) => v as u16,
+          _ => {
+            return Err(Cow::Borrowed(
+              "Invalid `CombineOptions`.`maxColors` property",
+            ))
+          }
+        },
+        native::valuetype::Null | native::valuetype::Undefined => 32,
+        _ => {
+          return Err(Cow::Borrowed(
+            "Invalid `CombineOptions`.`maxColors` property",
+          ))
+        }
+      };
+
+      let vdither = native::get_named_property(env, v, "dither")?;
+      let mut dither = match native::value_type(env, vdither)? {
+        native::valuetype::String => gif::Dither::from_napi(env, vdither)?,
+        native::valuetype::Null | native::valuetype::Undefined => gif::Dither::default(),
+        _ => return Err(Cow::Borrowed("Invalid `CombineOptions`.`dither` property")),
+      };
+
+      let vbayer_scale = native::get_named_property(env, v, "bayerScale")?;
+      match native::value_type(env, vbayer_scale)? {
+        native::valuetype::Number => match native::get_value_double(env, vbayer_scale)? {
+          #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+          v if v.fract() == 0_f64 && (0_f64..=5_f64).contains(&v) => {
+            if let gif::Dither::Bayer(scale) = &mut dither {
+              *scale = v as u8;
+            }
+          }
+          _ => {
+            return Err(Cow::Borrowed(
+              "Invalid `CombineOptions`.`bayerScale` property",
+            ))
+          }
+        },
+        native::valuetype::Null | native::valuetype::Undefined => {}
+        _ => {
+          return Err(Cow::Borrowed(
+            "Invalid `CombineOptions`.`bayerScale` property",
+          ))
+        }
+      }
+
+      let vstats = native::get_named_property(env, v, "statsMode")?;
+      let stats = match native::value_type(env, vstats)? {
+        native::valuetype::String => StatsMode::from_napi(env, vstats)?,
+        native::valuetype::Null | native::valuetype::Undefined => StatsMode::default(),
+        _ => {
+          return Err(Cow::Borrowed(
+            "Invalid `CombineOptions`.`statsMode` property",
+          ))
+        }
+      };
+
+      let vpalette = native::get_named_property(env, v, "palette")?;
+      let palette = match native::value_type(env, vpalette)? {
+        native::valuetype::Null | native::valuetype::Undefined => None,
+        _ => Some(UserPalette::from_napi(env, vpalette)?),
+      };
+
+      let vpalette_mode = native::get_named_property(env, v, "paletteMode")?;
+      let mut palette_mode = match native::value_type(env, vpalette_mode)? {
+        native::valuetype::String => PaletteMode::from_napi(env, vpalette_mode)?,
+        // Streamed frames are encoded as they come, so unless the palette is
+        // known upfront each one carries its own color table
+        native::valuetype::Null | native::valuetype::Undefined if stream && palette.is_none() => {
+          PaletteMode::Local
+        }
+        native::valuetype::Null | native::valuetype::Undefined => PaletteMode::default(),
+        _ => {
+          return Err(Cow::Borrowed(
+            "Invalid `CombineOptions`.`paletteMode` property",
+          ))
+        }
+      };
+
+      let vthreshold = native::get_named_property(env, v, "paletteThreshold")?;
+      match native::value_type(env, vthreshold)? {
+        native::valuetype::Number => match native::get_value_double(env, vthreshold)? {
+          v if v.is_finite() && v >= 0_f64 => {
+            if let PaletteMode::Hybrid(threshold) = &mut palette_mode {
+              *threshold = v;
+            }
+          }
+          _ => {
+            return Err(Cow::Borrowed(
+              "Invalid `CombineOptions`.`paletteThreshold` property",
+            ))
+          }
+        },
+        native::valuetype::Null | native::valuetype::Undefined => {}
+        _ => {
+          return Err(Cow::Borrowed(
+            "Invalid `CombineOptions`.`paletteThreshold` property",
+          ))
+        }
+      }
+
+      let valpha_threshold = native::get_named_property(env, v, "alphaThreshold")?;
+      let alpha_threshold = match native::value_type(env, valpha_threshold)? {
+        native::valuetype::Number => match native::get_value_double(env, valpha_threshold)? {
+          #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+          v if v.fract() == 0_f64 && (0_f64..=255_f64).contains(&v) => v as u8,
+          _ => {
+            return Err(Cow::Borrowed(
+              "Invalid `CombineOptions`.`alphaThreshold` property",
+            ))
+          }
+        },
+        native::valuetype::Null | native::valuetype::Undefined => 128,
+        _ => {
+          return Err(Cow::Borrowed(
+            "Invalid `CombineOptions`.`alphaThreshold` property",
+          ))
+        }
+      };
+
+      let voptimize = native::get_named_property(env, v, "optimize")?;
+      let optimize = match native::value_type(env, voptimize)? {
+        native::valuetype::Boolean => native::get_value_bool(env, voptimize)?,
+        native::valuetype::Null | native::valuetype::Undefined => true,
+        _ => {
+          return Err(Cow::Borrowed(
+            "Invalid `CombineOptions`.`optimize` property",
+          ))
+        }
+      };
+
+      let vmax_bytes = native::get_named_property(env, v, "maxBytes")?;
+      let max_bytes = match native::value_type(env, vmax_bytes)? {
+        native::valuetype::Number => match native::get_value_double(env, vmax_bytes)? {
+          #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+          v if v.fract() == 0_f64 && v >= 1_f64 && v <= 2_f64.powi(53) => Some(v as u64),
+          _ => {
+            return Err(Cow::Borrowed(
+              "Invalid `CombineOptions`.`maxBytes` property",
+            ))
+          }
+        },
+        native::valuetype::Null | native::valuetype::Undefined => None,
+        _ => {
+          return Err(Cow::Borrowed(
+            "Invalid `CombineOptions`.`maxBytes` property",
+          ))
+        }
+      };
+
+      let vlossy = native::get_named_property(env, v, "lossy")?;
+      let lossy = match native::value_type(env, vlossy)? {
+        native::valuetype::Number => match native::get_value_double(env, vlossy)? {
+          #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+          v if v.fract() == 0_f64 && (0_f64..=255_f64).contains(&v) => v as u8,
+          _ => return Err(Cow::Borrowed("Invalid `CombineOptions`.`lossy` property")),
+        },
+        native::valuetype::Null | native::valuetype::Undefined => 0,
+        _ => return Err(Cow::Borrowed("Invalid `CombineOptions`.`lossy` property")),
+      };
+
+      let vpixel_format = native::get_named_property(env, v, "pixelFormat")?;
+      let pixel_format = match native::value_type(env, vpixel_format)? {
+        native::valuetype::String => PixelFormat::from_napi(env, vpixel_format)?,
+        native::valuetype::Null | native::valuetype::Undefined => PixelFormat::default(),
+        _ => {
+          return Err(Cow::Borrowed(
+            "Invalid `CombineOptions`.`pixelFormat` property",
+          ))
+        }
+      };
+
+      if pixel_format == PixelFormat::Indexed && palette.is_none() {
+        return Err(Cow::Borrowed(
+          "The indexed pixel format requires a fixed `CombineOptions`.`palette`",
+        ));
+      }
+      if palette.is_some() && palette_mode != PaletteMode::Global {
+        return Err(Cow::Borrowed(
+          "A fixed `CombineOptions`.`palette` requires the `global` `CombineOptions`.`paletteMode`",
+        ));
+      }
+      if stream && palette.is_none() && palette_mode != PaletteMode::Local {
+        return Err(Cow::Borrowed(
+          "A streamed output requires either the `local` `CombineOptions`.`paletteMode` or a fixed `CombineOptions`.`palette`",
+        ));
+      }
+      if stream && output.is_some() {
+        return Err(Cow::Borrowed(
+          "`CombineOptions`.`stream` and `CombineOptions`.`output` are mutually exclusive",
+        ));
+      }
+      if stream && max_bytes.is_some() {
+        return Err(Cow::Borrowed(
+          "`CombineOptions`.`stream` and `CombineOptions`.`maxBytes` are mutually exclusive",
+        ));
+      }
+
       Ok(Self {
         width,
         height,
         fps,
         scale,
         repeat,
+        backend,
+        stream,
+        output,
+        max_colors,
+        dither,
+        stats,
+        palette_mode,
+        palette,
+        alpha_threshold,
+        optimize,
+        max_bytes,
+        lossy,
+        pixel_format,
       })
     } else {
       Err(Cow::Borrowed("Invalid CombineOptions"))
@@ -101,10 +365,651 @@ impl FromNapi for CombineOptions {
   }
 }
 
+#[derive(Default)]
+pub(crate) struct WriteOptions {
+  pub(crate) delay_ms: Option<f64>,
+}
+
+impl FromNapi for WriteOptions {
+  fn from_napi(env: native::env, v: native::value) -> Result<Self> {
+    match native::value_type(env, v)? {
+      native::valuetype::Object => {
+        let vdelay = native::get_named_property(env, v, "delayMs")?;
+        let delay_ms = match native::value_type(env, vdelay)? {
+          native::valuetype::Number => match native::get_value_double(env, vdelay)? {
+            v if v.is_finite() && v >= 0_f64 => Some(v),
+            _ => return Err(Cow::Borrowed("Invalid `WriteOptions`.`delayMs` property")),
+          },
+          native::valuetype::Null | native::valuetype::Undefined => None,
+          _ => return Err(Cow::Borrowed("Invalid `WriteOptions`.`delayMs` property")),
+        };
+
+        Ok(Self { delay_ms })
+      }
+      native::valuetype::Null | native::valuetype::Undefined => Ok(Self::default()),
+      _ => Err(Cow::Borrowed("Invalid WriteOptions")),
+    }
+  }
+}
+
+impl Repeat {
+  fn count(&self) -> u16 {
+    match self {
+      Self::Infinite => 0,
+      Self::Once => 1,
+      Self::Exact(v) => *v,
+    }
+  }
+}
+
+/// Converts the display duration into a delay advancing the timestamp.
+/// Delays are derived from the accumulated timestamps, so rounding errors do
+/// not add up over the animation
+fn advance(pts: &mut f64, duration: f64) -> u16 {
+  let next = *pts + duration;
+  #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+  let delay =
+    ((next / 10_f64).round() - (*pts / 10_f64).round()).clamp(0_f64, f64::from(u16::MAX)) as u16;
+  *pts = next;
+  delay
+}
+
+/// Maps frames onto color tables and encodes them
+struct FrameWriter {
+  width: u16,
+  height: u16,
+  max_colors: usize,
+  dither: gif::Dither,
+  alpha_threshold: u8,
+  /// Crops the frames to the changed area, picking the disposal instead
+  optimizer: Option<gif::Optimizer>,
+  indices: Vec<u8>,
+}
+
+impl FrameWriter {
+  /// Disposal of a full-canvas frame followed by `next`. The canvas is only
+  /// cleared for a next frame holding transparent pixels, through which the
+  /// frame would show otherwise
+  fn disposal(&self, next: Option<&[u8]>) -> gif::Disposal {
+    match next {
+      Some(v) if v.chunks_exact(4).any(|v| v[3] < self.alpha_threshold) => {
+        gif::Disposal::Background
+      }
+      _ => gif::Disposal::Unspecified,
+    }
+  }
+
+  /// Encodes the frame onto the `global` palette, or onto a local palette
+  /// generated from the frame itself when omitted
+  fn write(
+    &mut self,
+    encoder: &mut gif::Encoder<Sink>,
+    rgba: &[u8],
+    delay: u16,
+    disposal: gif::Disposal,
+    global: Option<&mut gif::Mapper>,
+  ) -> Result<()> {
+    let mut local = None;
+    let is_local = global.is_none();
+    let mapper = match global {
+      Some(v) => v,
+      None => {
+        let mut histogram = gif::Histogram::new(self.alpha_threshold);
+        if self.optimizer.is_some() {
+          histogram.reserve_transparent();
+        }
+        histogram.add(rgba);
+        local.insert(histogram.mapper(self.max_colors))
+      }
+    };
+    mapper.remap(
+      rgba,
+      usize::from(self.width),
+      self.dither,
+      &mut self.indices,
+    );
+    let frame = gif::Frame {
+      rect: gif::Rect {
+        left: 0,
+        top: 0,
+        width: self.width,
+        height: self.height,
+      },
+      delay,
+      disposal,
+      transparent: mapper.transparent(),
+      palette: is_local.then(|| mapper.palette()),
+      indices: &self.indices,
+    };
+    match &mut self.optimizer {
+      Some(optimizer) => optimizer.push(encoder, &frame, mapper.palette()),
+      None => encoder.write_frame(&frame),
+    }
+  }
+
+  /// Writes the frame held back by the optimizer, if any
+  fn flush(&mut self, encoder: &mut gif::Encoder<Sink>) -> Result<()> {
+    match &mut self.optimizer {
+      Some(optimizer) => optimizer.flush(encoder),
+      None => Ok(()),
+    }
+  }
+}
+
+pub(crate) struct NativeCombine {
+  fps: f64,
+  repeat: u16,
+  lossy: u8,
+  palette_mode: PaletteMode,
+  stats: StatsMode,
+  /// The global palette when known upfront
+  fixed: Option<gif::Mapper>,
+  format: PixelFormat,
+  /// The palette indexed frames refer to
+  palette: Option<UserPalette>,
+  resizer: Option<gif::Resizer>,
+  /// Presentation timestamp of the next frame in milliseconds
+  pts: f64,
+  writer: FrameWriter,
+  pending: Pending,
+  /// Frame held back while encoding right away, as its disposal depends on
+  /// the next one. The optimizer holds the frames back instead, and opaque
+  /// frames are never held
+  held: Option<PendingFrame>,
+}
+
+enum Pending {
+  /// Global and hybrid palettes are generated once every frame is written
+  Buffered {
+    sink: Sink,
+    histogram: gif::Histogram,
+    frames: Vec<PendingFrame>,
+  },
+  /// Frames with local or fixed palettes are encoded right away
+  Encoding(gif::Encoder<Sink>),
+}
+
+struct PendingFrame {
+  rgba: Vec<u8>,
+  /// Display duration in milliseconds
+  duration: f64,
+}
+
+impl NativeCombine {
+  /// Sets up the encoder for the `CombineOptions`.`scale` output size
+  pub(crate) fn create(options: &CombineOptions, sink: Sink) -> Result<Self> {
+    let scale = options.scale.unwrap_or((options.width, options.height));
+    let (Ok(width), Ok(height)) = (u16::try_from(scale.0), u16::try_from(scale.1)) else {
+      return Err(Cow::Borrowed("The output size exceeds the GIF limits"));
+    };
+    let resizer = (scale != (options.width, options.height)).then(|| {
+      gif::Resizer::new(
+        (options.width as usize, options.height as usize),
+        (scale.0 as usize, scale.1 as usize),
+      )
+    });
+    Self::new(options, width, height, resizer, sink)
+  }
+
+  fn new(
+    options: &CombineOptions,
+    width: u16,
+    height: u16,
+    resizer: Option<gif::Resizer>,
+    sink: Sink,
+  ) -> Result<Self> {
+    let repeat = options.repeat.count();
+    let fixed = options
+      .palette
+      .as_ref()
+      .map(|v| v.mapper(options.alpha_threshold, options.optimize));
+    let pending = match (options.palette_mode, &fixed) {
+      (PaletteMode::Local, _) | (_, Some(_)) => Pending::Encoding(
+        gif::Encoder::new(
+          sink,
+          width,
+          height,
+          fixed.as_ref().map(gif::Mapper::palette),
+          Some(repeat),
+        )?
+        .with_lossy(options.lossy),
+      ),
+      (PaletteMode::Global | PaletteMode::Hybrid(_), None) => Pending::Buffered {
+        sink,
+        histogram: gif::Histogram::new(options.alpha_threshold),
+        frames: Vec::new(),
+      },
+    };
+    Ok(Self {
+      fps: options.fps,
+      repeat,
+      lossy: options.lossy,
+      palette_mode: options.palette_mode,
+      stats: options.stats,
+      fixed,
+      format: options.pixel_format,
+      palette: options
+        .palette
+        .clone()
+        .filter(|_| options.pixel_format == PixelFormat::Indexed),
+      resizer,
+      pts: 0_f64,
+      writer: FrameWriter {
+        width,
+        height,
+        max_colors: usize::from(options.max_colors),
+        dither: options.dither,
+        alpha_threshold: options.alpha_threshold,
+        optimizer: options.optimize.then(|| gif::Optimizer::new(width, height)),
+        indices: Vec::new(),
+      },
+      pending,
+      held: None,
+    })
+  }
+
+  pub(crate) fn write(&mut self, buf: &[u8], options: &WriteOptions) -> Result<()> {
+    let mut frame = Vec::new();
+    self.format.to_rgba(buf, self.palette.as_ref(), &mut frame);
+    if let Some(resizer) = &mut self.resizer {
+      let mut resized = Vec::new();
+      resizer.resize(&frame, &mut resized);
+      frame = resized;
+    }
+    let duration = options.delay_ms.unwrap_or(1000_f64 / self.fps);
+
+    match &mut self.pending {
+      Pending::Buffered {
+        histogram, frames, ..
+      } => {
+        match (self.stats, frames.last()) {
+          (StatsMode::Diff, Some(previous)) => histogram.add_changed(&frame, &previous.rgba),
+          _ => histogram.add(&frame),
+        }
+        frames.push(PendingFrame {
+          rgba: frame,
+          duration,
+        });
+        Ok(())
+      }
+      Pending::Encoding(encoder) => {
+        if self.writer.optimizer.is_some() || self.writer.alpha_threshold == 0 {
+          let delay = advance(&mut self.pts, duration);
+          return self.writer.write(
+            encoder,
+            &frame,
+            delay,
+            gif::Disposal::Unspecified,
+            self.fixed.as_mut(),
+          );
+        }
+        let held = self.held.replace(PendingFrame {
+          rgba: frame,
+          duration,
+        });
+        match held {
+          Some(held) => {
+            let next = self.held.as_ref().map(|v| v.rgba.as_slice());
+            let disposal = self.writer.disposal(next);
+            let delay = advance(&mut self.pts, held.duration);
+            self
+              .writer
+              .write(encoder, &held.rgba, delay, disposal, self.fixed.as_mut())
+          }
+          None => Ok(()),
+        }
+      }
+    }
+  }
+
+  pub(crate) fn finish(mut self) -> Result<Output> {
+    let (sink, mut histogram, frames) = match self.pending {
+      Pending::Encoding(mut encoder) => {
+        if let Some(held) = self.held.take() {
+          let delay = advance(&mut self.pts, held.duration);
+          self.writer.write(
+            &mut encoder,
+            &held.rgba,
+            delay,
+            gif::Disposal::Unspecified,
+            self.fixed.as_mut(),
+          )?;
+        }
+        self.writer.flush(&mut encoder)?;
+        return encoder
+          .finish()?
+          .finish()
+          .map_err(|v| Cow::Owned(v.to_string()));
+      }
+      Pending::Buffered {
+        sink,
+        histogram,
+        frames,
+      } => (sink, histogram, frames),
+    };
+
+    if self.writer.optimizer.is_some() {
+      histogram.reserve_transparent();
+    }
+    let mut mapper = histogram.mapper(self.writer.max_colors);
+    let mut encoder = gif::Encoder::new(
+      sink,
+      self.writer.width,
+      self.writer.height,
+      Some(mapper.palette()),
+      Some(self.repeat),
+    )?
+    .with_lossy(self.lossy);
+    for (i, frame) in frames.iter().enumerate() {
+      let delay = advance(&mut self.pts, frame.duration);
+      let disposal = self
+        .writer
+        .disposal(frames.get(i + 1).map(|v| v.rgba.as_slice()));
+      // Hybrid palettes fall back to a local table for the frames the
+      // global one does not represent well enough
+      let global = match self.palette_mode {
+        PaletteMode::Hybrid(threshold) if mapper.error(&frame.rgba) > threshold => None,
+        _ => Some(&mut mapper),
+      };
+      self
+        .writer
+        .write(&mut encoder, &frame.rgba, delay, disposal, global)?;
+    }
+    self.writer.flush(&mut encoder)?;
+    encoder
+      .finish()?
+      .finish()
+      .map_err(|v| Cow::Owned(v.to_string()))
+  }
+
+  /// Stops encoding without finishing the GIF
+  fn abort(self) {
+    match self.pending {
+      Pending::Buffered { sink, .. } => sink.abort(),
+      Pending::Encoding(encoder) => encoder.into_inner().abort(),
+    }
+  }
+}
+
+pub(crate) enum Encoding {
+  Native(Box<NativeCombine>),
+  /// Frames are kept until `finish`, which encodes them until the output
+  /// fits `CombineOptions`.`maxBytes`
+  Fitting(Box<Fitting>),
+  Ffmpeg {
+    child: Child,
+    stdin: ChildStdin,
+    /// Drains the output concurrently, so `ffmpeg` never stalls on a full
+    /// pipe
+    drain: JoinHandle<(Sink, io::Result<u64>)>,
+  },
+}
+
+impl Encoding {
+  fn write(&mut self, buf: &[u8], options: &WriteOptions) -> Result<()> {
+    match self {
+      Self::Native(v) => v.write(buf, options),
+      Self::Fitting(v) => {
+        v.write(buf, options);
+        Ok(())
+      }
+      Self::Ffmpeg { stdin, .. } => stdin.write_all(buf).map_err(|v| Cow::Owned(v.to_string())),
+    }
+  }
+
+  fn finish(self) -> Result<Output> {
+    match self {
+      Self::Native(v) => v.finish(),
+      Self::Fitting(v) => v.finish(),
+      Self::Ffmpeg {
+        mut child,
+        stdin,
+        drain,
+      } => {
+        drop(stdin);
+        let (sink, result) = drain
+          .join()
+          .map_err(|_| Cow::Borrowed("The output thread panicked"))?;
+        let _ = child.wait();
+        result
+          .and_then(|_| sink.finish())
+          .map_err(|v| Cow::Owned(v.to_string()))
+      }
+    }
+  }
+
+  /// Stops encoding without finishing the GIF, removing a partially written
+  /// output file
+  fn abort(self) {
+    match self {
+      Self::Native(v) => v.abort(),
+      Self::Fitting(v) => v.abort(),
+      Self::Ffmpeg {
+        mut child,
+        stdin,
+        drain,
+      } => {
+        // Killed first, so `ffmpeg` does not finish the GIF on the closed
+        // input
+        let _ = child.kill();
+        drop(stdin);
+        let _ = child.wait();
+        if let Ok((sink, _)) = drain.join() {
+          sink.abort();
+        }
+      }
+    }
+  }
+}
+
+/// Number of written frames buffered ahead of the encoder before `write`
+/// starts to wait
+const QUEUE_CAPACITY: u64 = 4;
+
+#[derive(Default)]
+struct QueueState {
+  frames: VecDeque<(Vec<u8>, WriteOptions)>,
+  pushed: u64,
+  taken: u64,
+  closed: bool,
+  error: Option<Cow<'static, str>>,
+  /// Pending writes, notified once their frame is accepted
+  waiting: Vec<(u64, native::Notifier)>,
+}
+
+/// Frames handed from the main thread over to the encoder thread
+#[derive(Default)]
+pub(crate) struct FrameQueue {
+  state: Mutex<QueueState>,
+  changed: Condvar,
+}
+
+impl FrameQueue {
+  fn lock(&self) -> MutexGuard<'_, QueueState> {
+    self.state.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  /// Enqueues a frame and returns its sequence number
+  fn push(&self, frame: Vec<u8>, options: WriteOptions) -> Result<u64> {
+    let mut state = self.lock();
+    if let Some(v) = &state.error {
+      return Err(v.clone());
+    }
+    if state.closed {
+      return Err(Cow::Borrowed("The Combine instance has already finished"));
+    }
+    state.frames.push_back((frame, options));
+    state.pushed += 1;
+    self.changed.notify_all();
+    Ok(state.pushed - 1)
+  }
+
+  /// Whether the frame `seq` fits into the queue. Fails once the encoder
+  /// has failed
+  fn accepted(state: &QueueState, seq: u64) -> Result<bool> {
+    match &state.error {
+      Some(v) => Err(v.clone()),
+      None => Ok(seq < state.taken + QUEUE_CAPACITY),
+    }
+  }
+
+  /// Number of frames enqueued so far
+  fn pushed(&self) -> u64 {
+    self.lock().pushed
+  }
+
+  fn is_accepted(&self, seq: u64) -> Result<bool> {
+    Self::accepted(&self.lock(), seq)
+  }
+
+  /// Drops `notifier` once the frame `seq` fits into the queue or the
+  /// encoder has failed
+  fn notify_accepted(&self, seq: u64, notifier: native::Notifier) {
+    let mut state = self.lock();
+    if let Ok(false) = Self::accepted(&state, seq) {
+      state.waiting.push((seq, notifier));
+    }
+  }
+
+  /// Blocks until a frame is available. Returns `None` once the queue is
+  /// closed and drained
+  fn take(&self) -> Option<(Vec<u8>, WriteOptions)> {
+    let mut state = self.lock();
+    loop {
+      if let Some(v) = state.frames.pop_front() {
+        state.taken += 1;
+        let taken = state.taken;
+        state
+          .waiting
+          .retain(|(seq, _)| *seq >= taken + QUEUE_CAPACITY);
+        self.changed.notify_all();
+        return Some(v);
+      }
+      if state.closed {
+        return None;
+      }
+      state = self
+        .changed
+        .wait(state)
+        .unwrap_or_else(PoisonError::into_inner);
+    }
+  }
+
+  fn error(&self) -> Option<Cow<'static, str>> {
+    self.lock().error.clone()
+  }
+
+  fn close(&self) {
+    self.lock().closed = true;
+    self.changed.notify_all();
+  }
+
+  fn fail(&self, error: Cow<'static, str>) {
+    let mut state = self.lock();
+    state.closed = true;
+    state.frames.clear();
+    state.error = Some(error);
+    state.waiting.clear();
+    self.changed.notify_all();
+  }
+}
+
+fn combine_worker(mut encoding: Encoding, queue: &FrameQueue) -> Result<Output> {
+  while let Some((frame, options)) = queue.take() {
+    if let Err(v) = encoding.write(&frame, &options) {
+      queue.fail(v.clone());
+      encoding.abort();
+      return Err(v);
+    }
+  }
+  // The queue fails once the instance is dropped unfinished
+  if let Some(v) = queue.error() {
+    encoding.abort();
+    return Err(v);
+  }
+  encoding.finish()
+}
+
 pub(crate) struct Combine {
   pub(crate) buf_size: usize,
-  pub(crate) stdin: Option<ChildStdin>,
-  pub(crate) stdout: ChildStdout,
+  pub(crate) backend: Backend,
+  pub(crate) queue: Arc<FrameQueue>,
+  /// Encoded output when streaming
+  pub(crate) chunks: Option<Arc<Chunks>>,
+  pub(crate) worker: Option<JoinHandle<Result<Output>>>,
+}
+
+impl Combine {
+  fn new(
+    buf_size: usize,
+    backend: Backend,
+    chunks: Option<Arc<Chunks>>,
+    encoding: Encoding,
+  ) -> Result<Self> {
+    let queue = Arc::new(FrameQueue::default());
+    let worker = {
+      let queue = Arc::clone(&queue);
+      let chunks = chunks.clone();
+      thread::Builder::new()
+        .name("easygif-combine".to_owned())
+        .spawn(move || {
+          let result = combine_worker(encoding, &queue);
+          if let (Some(chunks), Err(v)) = (chunks, &result) {
+            chunks.end(Some(v.clone()));
+          }
+          result
+        })
+        .map_err(|_| Cow::Borrowed("Failed to spawn the encoder thread"))?
+    };
+    Ok(Self {
+      buf_size,
+      backend,
+      queue,
+      chunks,
+      worker: Some(worker),
+    })
+  }
+
+  /// Hands the frame over to the encoder thread and returns its sequence
+  /// number
+  pub(crate) fn write(&mut self, buf: &[u8], options: WriteOptions) -> Result<u64> {
+    if self.backend == Backend::Ffmpeg && options.delay_ms.is_some() {
+      return Err(Cow::Borrowed(
+        "Per-frame delays are not supported by the ffmpeg backend",
+      ));
+    }
+    self.queue.push(buf.to_vec(), options)
+  }
+
+  /// Closes the queue. The returned worker yields the encoded GIF once every
+  /// written frame is processed
+  pub(crate) fn finish(&mut self) -> Result<JoinHandle<Result<Output>>> {
+    if self.worker.is_some() && self.queue.pushed() == 0 {
+      return Err(Cow::Borrowed("No frames were written"));
+    }
+    let worker = self
+      .worker
+      .take()
+      .ok_or(Cow::Borrowed("The Combine instance has already finished"))?;
+    self.queue.close();
+    Ok(worker)
+  }
+}
+
+impl Drop for Combine {
+  fn drop(&mut self) {
+    // An unfinished instance is abandoned, so the encoder thread stops
+    // without finishing the output
+    if self.worker.is_some() {
+      self
+        .queue
+        .fail(Cow::Borrowed("The Combine instance was dropped"));
+    }
+    self.queue.close();
+    if let Some(v) = &self.chunks {
+      v.cancel();
+    }
+  }
 }
 
 impl ToNapi for &mut Combine {
@@ -124,13 +1029,75 @@ impl ToNapi for &mut Combine {
 }
 
 extern "C" fn combine_finalize(_env: native::env, data: *mut c_void, _hint: *mut c_void) {
-  unsafe { drop_in_place(data) };
+  unsafe { drop_in_place(data.cast::<Combine>()) };
   unsafe {
     dealloc(data.cast(), Layout::new::<Combine>());
   };
 }
 
+fn combine_sink(options: &CombineOptions, chunks: Option<&Arc<Chunks>>) -> Result<Sink> {
+  match (chunks, &options.output) {
+    (Some(v), _) => Ok(Sink::Stream {
+      chunks: Arc::clone(v),
+      bytes: 0,
+    }),
+    (None, Some(path)) => Sink::create(path),
+    (None, None) => Ok(Sink::Memory(Vec::new())),
+  }
+}
+
 pub(crate) fn combine_native(options: &CombineOptions) -> Result<Combine> {
+  let buf_size =
+    options.width as usize * options.height as usize * options.pixel_format.bytes_per_pixel();
+  let chunks = options.stream.then(Arc::<Chunks>::default);
+  match options.backend {
+    Backend::Native => {
+      if !(options.fps.is_finite() && options.fps > 0_f64) {
+        return Err(Cow::Borrowed("Invalid `CombineOptions`.`fps` property"));
+      }
+      let sink = combine_sink(options, chunks.as_ref())?;
+      let encoding = match options.max_bytes {
+        Some(max_bytes) => Encoding::Fitting(Box::new(Fitting::new(options, max_bytes, sink)?)),
+        None => Encoding::Native(Box::new(NativeCombine::create(options, sink)?)),
+      };
+      Combine::new(buf_size, options.backend, chunks, encoding)
+    }
+    Backend::Ffmpeg => combine_ffmpeg(options, buf_size, chunks),
+  }
+}
+
+fn combine_ffmpeg(
+  options: &CombineOptions,
+  buf_size: usize,
+  chunks: Option<Arc<Chunks>>,
+) -> Result<Combine> {
+  if options.max_bytes.is_some() {
+    return Err(Cow::Borrowed(
+      "Target file sizes are not supported by the ffmpeg backend",
+    ));
+  }
+  if options.lossy > 0 {
+    return Err(Cow::Borrowed(
+      "Lossy compression is not supported by the ffmpeg backend",
+    ));
+  }
+  // `single` statistics paired with `new` palettes make a local palette per
+  // frame
+  let (stats, new) = match options.palette_mode {
+    PaletteMode::Global if options.palette.is_some() => {
+      return Err(Cow::Borrowed(
+        "Fixed palettes are not supported by the ffmpeg backend",
+      ))
+    }
+    PaletteMode::Global => (options.stats.as_str(), 0),
+    PaletteMode::Local => ("single", 1),
+    PaletteMode::Hybrid(_) => {
+      return Err(Cow::Borrowed(
+        "Hybrid palettes are not supported by the ffmpeg backend",
+      ))
+    }
+  };
+  let mut sink = combine_sink(options, chunks.as_ref())?;
   let scale = options.scale.unwrap_or((options.width, options.height));
   let mut child = Command::new("ffmpeg")
     .args([
@@ -141,7 +1108,7 @@ pub(crate) fn combine_native(options: &CombineOptions) -> Result<Combine> {
       "-f",
       "rawvideo",
       "-pix_fmt",
-      "rgba",
+      options.pixel_format.ffmpeg()?,
       "-r",
       &format!("{fps}", fps = options.fps),
       "-i",
@@ -150,21 +1117,41 @@ pub(crate) fn combine_native(options: &CombineOptions) -> Result<Combine> {
       "-f",
       "gif",
       "-loop",
-      &match options.repeat {
-        Repeat::Infinite => 0,
-        Repeat::Once => 1,
-        Repeat::Exact(v) => v,
-      }
-      .to_string(),
+      &options.repeat.count().to_string(),
+      "-gifflags",
+      if options.optimize {
+        "+offsetting+transdiff"
+      } else {
+        "-offsetting-transdiff"
+      },
       "-filter_complex",
       &format!(
         concat![
           "scale={w}x{h}",
           ":flags=lanczos,split[s0][s1]",
-          ";[s0]palettegen=max_colors=32[p];[s1][p]paletteuse=dither=bayer"
+          ";[s0]palettegen=max_colors={max_colors}:stats_mode={stats}",
+          ":reserve_transparent={reserve}[p]",
+          ";[s1][p]paletteuse=new={new}:dither={dither}:alpha_threshold={alpha}",
+          ":diff_mode={diff}"
         ],
         w = scale.0,
         h = scale.1,
+        max_colors = options.max_colors,
+        stats = stats,
+        reserve = u8::from(options.alpha_threshold > 0),
+        new = new,
+        alpha = options.alpha_threshold,
+        diff = if options.optimize {
+          "rectangle"
+        } else {
+          "none"
+        },
+        dither = match options.dither {
+          gif::Dither::None => Cow::Borrowed("none"),
+          gif::Dither::Bayer(v) => Cow::Owned(format!("bayer:bayer_scale={v}")),
+          gif::Dither::FloydSteinberg => Cow::Borrowed("floyd_steinberg"),
+          gif::Dither::Sierra => Cow::Borrowed("sierra2_4a"),
+        },
       ),
       "-",
     ])
@@ -173,19 +1160,31 @@ pub(crate) fn combine_native(options: &CombineOptions) -> Result<Combine> {
     .stderr(Stdio::null())
     .spawn()
     .map_err(|_| Cow::Borrowed("Failed to spawn combine channel"))?;
-  Ok(Combine {
-    buf_size: scale.0 as usize * scale.1 as usize * 4_usize,
-    stdin: Some(
-      child
-        .stdin
-        .take()
-        .ok_or(Cow::Borrowed("Failed to take the input handle"))?,
-    ),
-    stdout: child
-      .stdout
-      .take()
-      .ok_or(Cow::Borrowed("Failed to take the input handle"))?,
-  })
+  let stdin = child
+    .stdin
+    .take()
+    .ok_or(Cow::Borrowed("Failed to take the input handle"))?;
+  let mut stdout = child
+    .stdout
+    .take()
+    .ok_or(Cow::Borrowed("Failed to take the output handle"))?;
+  let drain = thread::Builder::new()
+    .name("easygif-ffmpeg-output".to_owned())
+    .spawn(move || {
+      let result = io::copy(&mut stdout, &mut sink);
+      (sink, result)
+    })
+    .map_err(|_| Cow::Borrowed("Failed to spawn the output thread"))?;
+  Combine::new(
+    buf_size,
+    options.backend,
+    chunks,
+    Encoding::Ffmpeg {
+      child,
+      stdin,
+      drain,
+    },
+  )
 }
 
 extern "C" fn combine_constructor(_env: native::env, _value: native::value) -> native::value {
@@ -217,71 +1216,117 @@ extern "C" fn combine_alloc(env: native::env, info: native::callback_info) -> na
   native::unwrap_throw!(env, native::create_buffer(env, ctx.buf_size))
 }
 
-extern "C" fn combine_write(env: native::env, info: native::callback_info) -> native::value {
-  let (this, args, _) = native::unwrap_throw!(env, native::get_cb_info(env, info));
-  let combine =
-    unsafe { &mut *(native::unwrap_throw!(env, native::unwrap(env, this)) as *mut Combine) };
+struct CombineWriteContext {
+  queue: Arc<FrameQueue>,
+  seq: u64,
+  deferred: native::deferred,
+}
+
+/// Called on the main thread once the encoder thread accepted the frame or
+/// failed
+extern "C" fn combine_write_complete(
+  env: native::env,
+  _js_callback: native::value,
+  data: *mut c_void,
+  _: *mut c_void,
+) {
+  let ctx = unsafe { &mut *data.cast::<CombineWriteContext>() };
+  if !env.is_null() {
+    let _ = match ctx.queue.is_accepted(ctx.seq) {
+      Ok(_) => native::resolve_deferred(env, ctx.deferred, native::undefined(env)),
+      Err(v) => native::create_string_utf8(env, &v)
+        .and_then(|v| native::reject_deferred(env, ctx.deferred, v)),
+    };
+  }
 
-  let Some(v) = args.into_iter().next() else {
-    let msg = "Function call expects exactly one argument";
-    native::throw_error(env, msg, msg);
-    return null_mut();
+  unsafe { drop_in_place(data.cast::<CombineWriteContext>()) };
+  unsafe {
+    dealloc(data.cast(), Layout::new::<CombineWriteContext>());
   };
+}
 
-  if !native::unwrap_throw!(env, native::is_buffer(env, v)) {
-    let msg = "The first argument is expected to be of type `Buffer`";
-    native::throw_error(env, msg, msg);
-    return null_mut();
+fn combine_write_promise(
+  env: native::env,
+  info: native::callback_info,
+  deferred: native::deferred,
+) -> Result<()> {
+  let (this, args, _) = native::get_cb_info(env, info)?;
+  let combine = unsafe { &mut *(native::unwrap(env, this)? as *mut Combine) };
+
+  let mut args = args.into_iter();
+  let v = args
+    .next()
+    .ok_or(Cow::Borrowed("Function call expects at least one argument"))?;
+  if !native::is_buffer(env, v)? {
+    return Err(Cow::Borrowed(
+      "The first argument is expected to be of type `Buffer`",
+    ));
   }
 
-  let buf = native::unwrap_throw!(env, native::get_buffer_info(env, v));
+  let buf = native::get_buffer_info(env, v)?;
   if buf.len() != combine.buf_size {
-    let msg = format!(
+    return Err(Cow::Owned(format!(
       "The buffer is expected to be of size {buf_size}",
       buf_size = combine.buf_size,
-    );
-    native::throw_error(env, &msg, &msg);
-    return null_mut();
+    )));
   }
 
-  let Some(stdin) = &mut combine.stdin else {
-    let msg = "The Combine instance has already finished";
-    native::throw_error(env, msg, msg);
-    return null_mut();
+  let options = match args.next() {
+    Some(v) => WriteOptions::from_napi(env, v)?,
+    None => WriteOptions::default(),
   };
 
-  native::unwrap_throw!(
+  let seq = combine.write(buf, options)?;
+  if combine.queue.is_accepted(seq)? {
+    return native::resolve_deferred(env, deferred, native::undefined(env));
+  }
+
+  let result = Box::leak(Box::new(CombineWriteContext {
+    queue: Arc::clone(&combine.queue),
+    seq,
+    deferred,
+  }));
+  let notifier = native::Notifier::new(
     env,
-    stdin.write_all(buf).map_err(|v| Cow::Owned(v.to_string()))
-  );
-  native::null(env)
+    "Wait for the GIF encoder to accept a frame",
+    addr_of_mut!(*result).cast(),
+    combine_write_complete,
+  )
+  .map_err(|_| Cow::Borrowed("Failed to create a threadsafe_function"))?;
+  combine.queue.notify_accepted(seq, notifier);
+  Ok(())
 }
 
-fn combine_finish_native(combine: &'static mut Combine) -> Result<Vec<u8>> {
-  let stdin = combine
-    .stdin
-    .take()
-    .ok_or(Cow::Borrowed("The Combine instance has already finished"))?;
-  drop(stdin);
+extern "C" fn combine_write(env: native::env, info: native::callback_info) -> native::value {
+  let (promise, deferred) = native::unwrap_throw!(env, native::create_promise(env));
 
-  let mut buf = Vec::<u8>::new();
-  combine
-    .stdout
-    .read_to_end(&mut buf)
-    .map_err(|v| Cow::Owned(v.to_string()))?;
-  Ok(buf)
+  if !deferred.is_null() {
+    if let Err(v) = combine_write_promise(env, info, deferred) {
+      native::unwrap_throw!(
+        env,
+        native::create_string_utf8(env, &v).and_then(|v| native::reject_deferred(env, deferred, v))
+      );
+    }
+  }
+
+  promise
 }
 
 struct CombineFinishContext {
-  combine: &'static mut Combine,
+  worker: Option<JoinHandle<Result<Output>>>,
   async_work: native::async_work,
   deferred: native::deferred,
-  result: Result<Vec<u8>>,
+  result: Result<Output>,
 }
 
 extern "C" fn combine_finish_execute(_env: native::env, data: *mut c_void) {
   let ctx = unsafe { &mut *data.cast::<CombineFinishContext>() };
-  ctx.result = combine_finish_native(ctx.combine);
+  ctx.result = match ctx.worker.take() {
+    Some(v) => v
+      .join()
+      .unwrap_or(Err(Cow::Borrowed("The encoder thread panicked"))),
+    None => Err(Cow::Borrowed("The Combine instance has already finished")),
+  };
 }
 
 extern "C" fn combine_finish_complete(
@@ -290,8 +1335,9 @@ extern "C" fn combine_finish_complete(
   data: *mut c_void,
 ) {
   let ctx = unsafe { &mut *data.cast::<CombineFinishContext>() };
-  let _ = match &ctx.result {
-    Ok(v) => native::create_buffer_copy(env, v)
+  let _ = match &mut ctx.result {
+    Ok(v) => v
+      .to_napi(env)
       .and_then(|v| native::resolve_deferred(env, ctx.deferred, v)),
     Err(v) => {
       native::create_string_utf8(env, v).and_then(|v| native::reject_deferred(env, ctx.deferred, v))
@@ -314,7 +1360,7 @@ fn combine_finish_promise(
   let combine = unsafe { &mut *(native::unwrap(env, this)? as *mut Combine) };
 
   let result = Box::leak(Box::new(CombineFinishContext {
-    combine,
+    worker: Some(combine.finish()?),
     async_work: null_mut(),
     deferred,
     result: Err(Cow::Borrowed("")),
@@ -350,9 +1396,124 @@ pub(crate) extern "C" fn combine_finish(
   promise
 }
 
+fn combine_chunk(env: native::env, chunk: Option<&Vec<u8>>) -> Result<native::value> {
+  let result = native::create_object(env)?;
+  let value = match chunk {
+    Some(v) => native::create_buffer_copy(env, v)?,
+    None => native::undefined(env),
+  };
+  native::set_named_property(env, result, "value", value)?;
+  native::set_named_property(env, result, "done", chunk.is_none())?;
+  Ok(result)
+}
+
+struct CombineNextContext {
+  chunks: Arc<Chunks>,
+  iterator: native::nref,
+  deferred: native::deferred,
+}
+
+/// Called on the main thread once the encoder thread produced a chunk or
+/// ended the output
+extern "C" fn combine_next_complete(
+  env: native::env,
+  _js_callback: native::value,
+  data: *mut c_void,
+  _: *mut c_void,
+) {
+  let ctx = unsafe { &mut *data.cast::<CombineNextContext>() };
+  if !env.is_null() {
+    let _ = match ctx.chunks.take() {
+      Ok(v) => {
+        combine_chunk(env, v.as_ref()).and_then(|v| native::resolve_deferred(env, ctx.deferred, v))
+      }
+      Err(v) => native::create_string_utf8(env, &v)
+        .and_then(|v| native::reject_deferred(env, ctx.deferred, v)),
+    };
+    let _ = native::delete_reference(env, ctx.iterator);
+  }
+
+  unsafe { drop_in_place(data.cast::<CombineNextContext>()) };
+  unsafe {
+    dealloc(data.cast(), Layout::new::<CombineNextContext>());
+  };
+}
+
+fn combine_next_promise(
+  env: native::env,
+  info: native::callback_info,
+  deferred: native::deferred,
+) -> Result<()> {
+  let (this, _, data) = native::get_cb_info(env, info)?;
+  let combine = unsafe { &mut *(data as *mut Combine) };
+  let chunks = combine.chunks.as_ref().ok_or(Cow::Borrowed(
+    "The output is only readable with `CombineOptions`.`stream` set",
+  ))?;
+
+  if let Some(v) = chunks.try_take() {
+    return combine_chunk(env, v?.as_ref())
+      .and_then(|v| native::resolve_deferred(env, deferred, v));
+  }
+
+  let result = Box::leak(Box::new(CombineNextContext {
+    chunks: Arc::clone(chunks),
+    iterator: native::create_reference(env, this)?,
+    deferred,
+  }));
+  let notifier = native::Notifier::new(
+    env,
+    "Read the next chunk of the encoded GIF",
+    addr_of_mut!(*result).cast(),
+    combine_next_complete,
+  )
+  .map_err(|_| Cow::Borrowed("Failed to create a threadsafe_function"))?;
+  chunks.notify(notifier);
+  Ok(())
+}
+
+extern "C" fn combine_async_next(env: native::env, info: native::callback_info) -> native::value {
+  let (promise, deferred) = native::unwrap_throw!(env, native::create_promise(env));
+
+  if !deferred.is_null() {
+    if let Err(v) = combine_next_promise(env, info, deferred) {
+      native::unwrap_throw!(
+        env,
+        native::create_string_utf8(env, &v).and_then(|v| native::reject_deferred(env, deferred, v))
+      );
+    }
+  }
+
+  promise
+}
+
+/// Creates an iterator object over the encoded output holding onto the
+/// `Combine` instance, so the instance is not collected while being read
+extern "C" fn combine_async_iter(env: native::env, info: native::callback_info) -> native::value {
+  let (this, _, _) = native::unwrap_throw!(env, native::get_cb_info(env, info));
+  let inner = native::unwrap_throw!(env, native::unwrap(env, this));
+  let object = native::unwrap_throw!(env, native::create_object(env));
+  native::unwrap_throw!(
+    env,
+    native::set_named_property(
+      env,
+      object,
+      "next",
+      native::Callback("next", inner, combine_async_next)
+    )
+  );
+  native::unwrap_throw!(
+    env,
+    native::set_named_property(env, object, "combine", this)
+  );
+  object
+}
+
 pub(crate) fn combine_struct(env: native::env) -> Result<native::value> {
   static mut COMBINE_STRUCT: native::value = null_mut();
   if unsafe { COMBINE_STRUCT }.is_null() {
+    let global = native::global(env);
+    let symbol = native::get_named_property(env, global, "Symbol")?;
+    let symbol_async_iterator = native::get_named_property(env, symbol, "asyncIterator")?;
     let combine_struct = native::define_class(
       env,
       "Combine",
@@ -389,6 +1550,16 @@ pub(crate) fn combine_struct(env: native::env) -> Result<native::value> {
           attributes: native::property_attribute::Enumerable,
           data: null_mut(),
         },
+        native::property_descriptor {
+          utf8name: null_mut(),
+          name: symbol_async_iterator,
+          method: Some(combine_async_iter),
+          getter: None,
+          setter: None,
+          value: null_mut(),
+          attributes: native::property_attribute::Enumerable,
+          data: null_mut(),
+        },
       ],
     )?;
     unsafe { COMBINE_STRUCT = native::create_reference(env, combine_struct)? };
@@ -2,21 +2,736 @@ use std::{
   alloc::{dealloc, Layout},
   borrow::Cow,
   ffi::c_void,
-  io::{ErrorKind, Read},
+  io::{self, ErrorKind, Read},
+  mem::take,
   process::{ChildStdout, Command, Stdio},
   ptr::{addr_of_mut, drop_in_place, null, null_mut},
+  sync::Arc,
+  thread,
 };
 
 use crate::{
+  gif,
   napi::native::{self, FromNapi, ToNapi},
   Result,
 };
 
-use super::Probe;
+use super::{
+  palette_to_napi, Backend, Input, PixelFormat, Probe, Source, Stream, Until, UserPalette,
+};
+
+/// What the `start`, `end` and `step` of `ExtractOptions` count
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RangeUnit {
+  /// Frame indices
+  #[default]
+  Index,
+  /// Milliseconds of the animation, as per the stored delays
+  Ms,
+}
+
+impl FromNapi for RangeUnit {
+  fn from_napi(env: native::env, v: native::value) -> Result<Self> {
+    match String::from_napi(env, v)?.as_str() {
+      "index" => Ok(Self::Index),
+      "ms" => Ok(Self::Ms),
+      v => Err(Cow::Owned(format!("Unknown range unit {v:?}"))),
+    }
+  }
+}
+
+/// How frames are fit into the `ExtractOptions`.`scale` size
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ScaleMode {
+  /// Exactly the size, ignoring the aspect ratio
+  #[default]
+  Stretch,
+  /// Within the size, preserving the aspect ratio
+  Fit,
+  /// Exactly the size, preserving the aspect ratio by cropping the overflow
+  Fill,
+}
+
+impl FromNapi for ScaleMode {
+  fn from_napi(env: native::env, v: native::value) -> Result<Self> {
+    match String::from_napi(env, v)?.as_str() {
+      "stretch" => Ok(Self::Stretch),
+      "fit" => Ok(Self::Fit),
+      "fill" => Ok(Self::Fill),
+      v => Err(Cow::Owned(format!("Unknown scale mode {v:?}"))),
+    }
+  }
+}
+
+impl FromNapi for gif::Filter {
+  fn from_napi(env: native::env, v: native::value) -> Result<Self> {
+    match String::from_napi(env, v)?.as_str() {
+      "lanczos" => Ok(Self::Lanczos),
+      "bilinear" => Ok(Self::Bilinear),
+      "nearest" => Ok(Self::Nearest),
+      v => Err(Cow::Owned(format!("Unknown filter {v:?}"))),
+    }
+  }
+}
+
+#[derive(Default)]
+pub(crate) struct ExtractOptions {
+  pub(crate) backend: Backend,
+  pub(crate) start: Option<f64>,
+  pub(crate) end: Option<f64>,
+  pub(crate) step: Option<f64>,
+  pub(crate) unit: RangeUnit,
+  pub(crate) scale: Option<(u32, u32)>,
+  pub(crate) filter: gif::Filter,
+  pub(crate) scale_mode: ScaleMode,
+  pub(crate) pixel_format: PixelFormat,
+  pub(crate) zero_copy: bool,
+  pub(crate) metadata: bool,
+  pub(crate) raw: bool,
+}
+
+/// The frames are resampled to `resized`, then cropped to `size` around
+/// the center
+struct Scaling {
+  resized: (u32, u32),
+  size: (u32, u32),
+}
+
+/// Reads an optional non-negative number property
+fn range_bound(env: native::env, v: native::value, name: &str) -> Result<Option<f64>> {
+  let vbound = native::get_named_property(env, v, name)?;
+  match native::value_type(env, vbound)? {
+    native::valuetype::Number => match native::get_value_double(env, vbound)? {
+      v if v.is_finite() && v >= 0_f64 => Ok(Some(v)),
+      _ => Err(Cow::Owned(format!(
+        "Invalid `ExtractOptions`.`{name}` property"
+      ))),
+    },
+    native::valuetype::Null | native::valuetype::Undefined => Ok(None),
+    _ => Err(Cow::Owned(format!(
+      "Invalid `ExtractOptions`.`{name}` property"
+    ))),
+  }
+}
+
+impl FromNapi for ExtractOptions {
+  fn from_napi(env: native::env, v: native::value) -> Result<Self> {
+    match native::value_type(env, v)? {
+      native::valuetype::Object => {
+        let vbackend = native::get_named_property(env, v, "backend")?;
+        let backend = match native::value_type(env, vbackend)? {
+          native::valuetype::String => Backend::from_napi(env, vbackend)?,
+          native::valuetype::Null | native::valuetype::Undefined => Backend::default(),
+          _ => return Err(Cow::Borrowed("Invalid `ExtractOptions`.`backend` property")),
+        };
+
+        let vunit = native::get_named_property(env, v, "unit")?;
+        let unit = match native::value_type(env, vunit)? {
+          native::valuetype::String => RangeUnit::from_napi(env, vunit)?,
+          native::valuetype::Null | native::valuetype::Undefined => RangeUnit::default(),
+          _ => return Err(Cow::Borrowed("Invalid `ExtractOptions`.`unit` property")),
+        };
+
+        let start = range_bound(env, v, "start")?;
+        let end = range_bound(env, v, "end")?;
+        let step = range_bound(env, v, "step")?;
+        let integral = |v: Option<f64>| !v.is_some_and(|v| v.fract() != 0_f64);
+        if unit == RangeUnit::Index && !(integral(start) && integral(end) && integral(step)) {
+          return Err(Cow::Borrowed(
+            "Frame indices of `ExtractOptions` are expected to be integers",
+          ));
+        }
+        if step == Some(0_f64) {
+          return Err(Cow::Borrowed("Invalid `ExtractOptions`.`step` property"));
+        }
+        if let (Some(start), Some(end)) = (start, end) {
+          if end < start {
+            return Err(Cow::Borrowed(
+              "`ExtractOptions`.`end` precedes `ExtractOptions`.`start`",
+            ));
+          }
+        }
+
+        let vscale = native::get_named_property(env, v, "scale")?;
+        let scale = match native::value_type(env, vscale)? {
+          native::valuetype::Object
+            if native::is_array(env, vscale)? && native::get_array_length(env, vscale)? == 2 =>
+          {
+            let scale = (
+              native::get_property(env, vscale, 0_usize)
+                .and_then(|v| native::get_value_uint32(env, v))?,
+              native::get_property(env, vscale, 1_usize)
+                .and_then(|v| native::get_value_uint32(env, v))?,
+            );
+            if scale.0 == 0 || scale.1 == 0 {
+              return Err(Cow::Borrowed("Invalid `ExtractOptions`.`scale` property"));
+            }
+            Some(scale)
+          }
+          native::valuetype::Null | native::valuetype::Undefined => None,
+          _ => return Err(Cow::Borrowed("Invalid `ExtractOptions`.`scale` property")),
+        };
+
+        let vfilter = native::get_named_property(env, v, "filter")?;
+        let filter = match native::value_type(env, vfilter)? {
+          native::valuetype::String => gif::Filter::from_napi(env, vfilter)?,
+          native::valuetype::Null | native::valuetype::Undefined => gif::Filter::default(),
+          _ => return Err(Cow::Borrowed("Invalid `ExtractOptions`.`filter` property")),
+        };
+
+        let vscale_mode = native::get_named_property(env, v, "scaleMode")?;
+        let scale_mode = match native::value_type(env, vscale_mode)? {
+          native::valuetype::String => ScaleMode::from_napi(env, vscale_mode)?,
+          native::valuetype::Null | native::valuetype::Undefined => ScaleMode::default(),
+          _ => {
+            return Err(Cow::Borrowed(
+              "Invalid `ExtractOptions`.`scaleMode` property",
+            ))
+          }
+        };
+
+        let vpixel_format = native::get_named_property(env, v, "pixelFormat")?;
+        let pixel_format = match native::value_type(env, vpixel_format)? {
+          native::valuetype::String => PixelFormat::from_napi(env, vpixel_format)?,
+          native::valuetype::Null | native::valuetype::Undefined => PixelFormat::default(),
+          _ => {
+            return Err(Cow::Borrowed(
+              "Invalid `ExtractOptions`.`pixelFormat` property",
+            ))
+          }
+        };
+
+        let vzero_copy = native::get_named_property(env, v, "zeroCopy")?;
+        let zero_copy = match native::value_type(env, vzero_copy)? {
+          native::valuetype::Boolean => native::get_value_bool(env, vzero_copy)?,
+          native::valuetype::Null | native::valuetype::Undefined => false,
+          _ => {
+            return Err(Cow::Borrowed(
+              "Invalid `ExtractOptions`.`zeroCopy` property",
+            ))
+          }
+        };
+
+        let vmetadata = native::get_named_property(env, v, "metadata")?;
+        let metadata = match native::value_type(env, vmetadata)? {
+          native::valuetype::Boolean => native::get_value_bool(env, vmetadata)?,
+          native::valuetype::Null | native::valuetype::Undefined => false,
+          _ => {
+            return Err(Cow::Borrowed(
+              "Invalid `ExtractOptions`.`metadata` property",
+            ))
+          }
+        };
+
+        let vraw = native::get_named_property(env, v, "raw")?;
+        let raw = match native::value_type(env, vraw)? {
+          native::valuetype::Boolean => native::get_value_bool(env, vraw)?,
+          native::valuetype::Null | native::valuetype::Undefined => false,
+          _ => return Err(Cow::Borrowed("Invalid `ExtractOptions`.`raw` property")),
+        };
+        if raw {
+          let conflict = if scale.is_some() {
+            Some("scale")
+          } else if pixel_format != PixelFormat::Rgba {
+            Some("pixelFormat")
+          } else if zero_copy {
+            Some("zeroCopy")
+          } else {
+            None
+          };
+          if let Some(v) = conflict {
+            return Err(Cow::Owned(format!(
+              "`ExtractOptions`.`raw` and `ExtractOptions`.`{v}` are mutually exclusive"
+            )));
+          }
+        }
+
+        Ok(Self {
+          backend,
+          start,
+          end,
+          step,
+          unit,
+          scale,
+          filter,
+          scale_mode,
+          pixel_format,
+          zero_copy,
+          metadata,
+          raw,
+        })
+      }
+      native::valuetype::Null | native::valuetype::Undefined => Ok(Self::default()),
+      _ => Err(Cow::Borrowed("Invalid ExtractOptions")),
+    }
+  }
+}
+
+impl ExtractOptions {
+  /// How the `width`×`height` frames are scaled, `None` keeping them as is
+  fn scaling(&self, width: u32, height: u32) -> Option<Scaling> {
+    let (w, h) = self.scale?;
+    let factor = match self.scale_mode {
+      ScaleMode::Stretch => None,
+      ScaleMode::Fit => {
+        Some((f64::from(w) / f64::from(width)).min(f64::from(h) / f64::from(height)))
+      }
+      ScaleMode::Fill => {
+        Some((f64::from(w) / f64::from(width)).max(f64::from(h) / f64::from(height)))
+      }
+    };
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let resized = factor.map_or((w, h), |f| {
+      let side = |v: u32| ((f64::from(v) * f).round() as u32).max(1);
+      (side(width), side(height))
+    });
+    let size = match self.scale_mode {
+      ScaleMode::Stretch | ScaleMode::Fit => resized,
+      ScaleMode::Fill => (w.min(resized.0), h.min(resized.1)),
+    };
+    (resized != (width, height) || size != resized).then_some(Scaling { resized, size })
+  }
+
+  /// The selected frames, `None` selecting every frame. Millisecond ranges
+  /// require the `delays` of every frame
+  fn selection(&self, delays: Option<&[u16]>) -> Option<Selection> {
+    if self.start.is_none() && self.end.is_none() && self.step.is_none() {
+      return None;
+    }
+    let start = self.start.unwrap_or(0_f64);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let selection = match (self.unit, delays) {
+      (RangeUnit::Index, _) => {
+        let count = delays.map_or(usize::MAX, <[u16]>::len);
+        Selection::Range {
+          start: start as usize,
+          end: self.end.map_or(count, |v| (v as usize).min(count)),
+          step: self.step.map_or(1, |v| v as usize),
+        }
+      }
+      // Rejected ahead for streams
+      (RangeUnit::Ms, None) => Selection::List(Vec::new()),
+      (RangeUnit::Ms, Some(delays)) => {
+        let timestamps = delays
+          .iter()
+          .scan(0_f64, |pts, &v| {
+            let ts = *pts;
+            *pts += f64::from(v) * 10_f64;
+            Some((ts, *pts))
+          })
+          .collect::<Vec<_>>();
+        let end = self.end.unwrap_or(f64::INFINITY);
+        Selection::List(match self.step {
+          // Every frame displayed within the range
+          None => timestamps
+            .iter()
+            .enumerate()
+            .filter(|(_, &(ts, next))| next > start && ts < end)
+            .map(|(i, _)| i)
+            .collect(),
+          // The frames displayed at every `step` from `start`, once each
+          Some(step) => {
+            let mut selection = Vec::new();
+            let mut t = start;
+            while t < end {
+              let i = timestamps.partition_point(|&(ts, _)| ts <= t);
+              let Some(&(_, next)) = i.checked_sub(1).and_then(|i| timestamps.get(i)) else {
+                break;
+              };
+              if t >= next {
+                break;
+              }
+              selection.push(i - 1);
+              t = start + ((next - start) / step).ceil() * step;
+            }
+            selection
+          }
+        })
+      }
+    };
+    Some(selection)
+  }
+}
+
+pub(crate) enum Frames {
+  Native(Box<gif::Decoder<Input>>),
+  Ffmpeg(ChildStdout),
+}
+
+/// Indices of the selected frames in ascending order
+enum Selection {
+  /// Every `step` frame from `start` on, ahead of `end`. The frame count
+  /// is not needed, so streams are selected this way
+  Range {
+    start: usize,
+    end: usize,
+    step: usize,
+  },
+  List(Vec<usize>),
+}
+
+impl Selection {
+  /// The `n`th selected index
+  fn get(&self, n: usize) -> Option<usize> {
+    match self {
+      Self::Range { start, end, step } => n
+        .checked_mul(*step)
+        .and_then(|v| v.checked_add(*start))
+        .filter(|v| v < end),
+      Self::List(v) => v.get(n).copied(),
+    }
+  }
+
+  /// Number of the selected indices ahead of `index`
+  fn count_before(&self, index: usize) -> usize {
+    match self {
+      Self::Range { start, end, step } => index.min(*end).saturating_sub(*start).div_ceil(*step),
+      Self::List(v) => v.partition_point(|&v| v < index),
+    }
+  }
+}
+
+/// A composited state is kept once this many frames pass without a
+/// keyframe
+const SNAPSHOT_INTERVAL: usize = 16;
+/// The memory the composited states may hold
+const SNAPSHOT_BUDGET: usize = 64 << 20;
+
+/// Checkpoints seeking resumes decoding from, collected while the frames
+/// are read for the first time
+#[derive(Default)]
+struct Keyframes {
+  /// Checkpoints ahead of the frames by index, ascending
+  checkpoints: Vec<(usize, gif::Checkpoint)>,
+  /// Number of frames read at least once
+  indexed: usize,
+  /// The memory held by the composited states
+  size: usize,
+}
+
+/// Resamples the decoded frames for `ExtractOptions`.`scale`
+struct Scaler {
+  resizer: gif::Resizer,
+  scaling: Scaling,
+  /// The resampled frame, when cropped
+  resized: Vec<u8>,
+}
+
+impl Scaler {
+  fn scale(&mut self, src: &[u8], dst: &mut Vec<u8>) {
+    let Scaling { resized, size } = self.scaling;
+    if resized == size {
+      self.resizer.resize(src, dst);
+      return;
+    }
+    self.resizer.resize(src, &mut self.resized);
+    let (left, top) = ((resized.0 - size.0) / 2, (resized.1 - size.1) / 2);
+    dst.clear();
+    for row in self
+      .resized
+      .chunks_exact(resized.0 as usize * 4)
+      .skip(top as usize)
+      .take(size.1 as usize)
+    {
+      dst.extend_from_slice(&row[left as usize * 4..][..size.0 as usize * 4]);
+    }
+  }
+}
+
+/// Structure of a frame as stored in the file
+#[derive(Clone, Copy)]
+struct FrameInfo {
+  index: usize,
+  /// Delay in centiseconds
+  delay: u16,
+  disposal: gif::Disposal,
+  rect: gif::Rect,
+  transparent: Option<u8>,
+  local_palette: bool,
+  interlaced: bool,
+}
 
 pub(crate) struct Extract {
-  pub(crate) stdout: ChildStdout,
+  pub(crate) frames: Frames,
   pub(crate) buf: Vec<u8>,
+  /// Size of the frames yielded
+  pub(crate) width: u32,
+  pub(crate) height: u32,
+  scaler: Option<Scaler>,
+  format: PixelFormat,
+  /// Maps the frames onto the global color table in the indexed format
+  pub(crate) mapper: Option<gif::Mapper>,
+  /// The scaled frame, ahead of the pixel format conversion
+  rgba: Vec<u8>,
+  /// Hands `buf` over to JS without copying it
+  zero_copy: bool,
+  /// Yields the frames along with their `info`
+  metadata: bool,
+  /// Yields the images as stored in the file instead of composited frames
+  raw: bool,
+  /// The last frame read
+  info: Option<FrameInfo>,
+  /// Start time of each frame in milliseconds, known ahead unless
+  /// streaming
+  timestamps: Vec<f64>,
+  /// End time of the frames timed so far while streaming
+  elapsed: f64,
+  /// The selected frames, if not every frame is
+  selection: Option<Selection>,
+  /// Number of the selected frames passed
+  selected: usize,
+  /// Index of the next frame to read
+  position: usize,
+  /// Not known ahead for streams
+  frame_count: Option<usize>,
+  /// The streamed input, if any
+  stream: Option<Arc<Stream>>,
+  keyframes: Keyframes,
+  /// Set while a frame is read off the main thread
+  pub(crate) reading: bool,
+}
+
+impl Extract {
+  /// `scaler` is only set when the frames are not scaled by the backend
+  fn new(
+    frames: Frames,
+    (width, height): (u32, u32),
+    scaler: Option<Scaler>,
+    mapper: Option<gif::Mapper>,
+    probe: &Probe,
+    options: &ExtractOptions,
+  ) -> Self {
+    let format = options.pixel_format;
+    // Raw images are handed over straight from the decoder
+    let size = if options.raw {
+      0
+    } else {
+      width as usize * height as usize * format.bytes_per_pixel()
+    };
+    Self {
+      frames,
+      buf: vec![0_u8; size],
+      width,
+      height,
+      scaler,
+      format,
+      mapper,
+      rgba: Vec::new(),
+      zero_copy: options.zero_copy,
+      metadata: options.metadata,
+      raw: options.raw,
+      info: None,
+      timestamps: probe
+        .known_delays()
+        .unwrap_or_default()
+        .iter()
+        .scan(0_f64, |ts, &v| {
+          let start = *ts;
+          *ts += f64::from(v) * 10_f64;
+          Some(start)
+        })
+        .collect(),
+      elapsed: 0_f64,
+      selection: options.selection(probe.known_delays()),
+      selected: 0,
+      position: 0,
+      frame_count: probe.known_delays().map(<[u16]>::len),
+      stream: match &probe.source {
+        Source::Stream(v) => Some(Arc::clone(v)),
+        Source::File | Source::Memory(_) => None,
+      },
+      keyframes: Keyframes::default(),
+      reading: false,
+    }
+  }
+
+  /// Index of the frame the next read ends at, if any is left selected
+  fn next_index(&self) -> Option<usize> {
+    match &self.selection {
+      Some(v) => v.get(self.selected),
+      None => Some(self.position),
+    }
+  }
+
+  /// What reading up to the frame at `index` waits for while streaming,
+  /// unless received already
+  fn awaiting(&self, index: Option<usize>) -> Option<(Arc<Stream>, Until)> {
+    let (stream, index) = (self.stream.as_ref()?, index?);
+    let until = match self.frames {
+      Frames::Native(_) => Until::Images(index),
+      // The frames `ffmpeg` outputs are not matched to the images received
+      Frames::Ffmpeg(_) => Until::Ended,
+    };
+    (!stream.is_reached(until)).then(|| (Arc::clone(stream), until))
+  }
+
+  /// Reads the next selected frame into `buf`. Returns `false` once there
+  /// are no frames left
+  pub(crate) fn read_frame(&mut self) -> Result<bool> {
+    let mut buf = take(&mut self.buf);
+    let result = self.read_frame_into(&mut buf);
+    self.buf = buf;
+    result
+  }
+
+  /// Reads the next selected frame into `out`, of the same size as `buf`
+  pub(crate) fn read_frame_into(&mut self, out: &mut [u8]) -> Result<bool> {
+    loop {
+      let wanted = match &self.selection {
+        Some(v) => match v.get(self.selected) {
+          Some(v) => v == self.position,
+          None => return Ok(false),
+        },
+        None => true,
+      };
+      if !self.next_frame(wanted.then_some(&mut *out))? {
+        return Ok(false);
+      }
+      self.position += 1;
+      if wanted {
+        self.selected += 1;
+        return Ok(true);
+      }
+    }
+  }
+
+  /// Reads the next frame into `out`, if any. Skipped frames are still
+  /// composited, as the later ones build upon them
+  fn next_frame(&mut self, out: Option<&mut [u8]>) -> Result<bool> {
+    match &mut self.frames {
+      Frames::Native(decoder) => {
+        let keyframes = &mut self.keyframes;
+        let checkpoint = if self.position == keyframes.indexed {
+          let composited = !self.raw
+            && keyframes
+              .checkpoints
+              .last()
+              .is_none_or(|v| self.position - v.0 >= SNAPSHOT_INTERVAL)
+            && keyframes.size < SNAPSHOT_BUDGET;
+          Some(decoder.checkpoint(composited)?)
+        } else {
+          None
+        };
+        if self.raw {
+          if decoder.next_image(out.is_some())?.is_none() {
+            return Ok(false);
+          }
+        } else {
+          let Some(v) = decoder.next_frame()? else {
+            return Ok(false);
+          };
+          if let Some(out) = out {
+            let v = match &mut self.scaler {
+              Some(scaler) => {
+                scaler.scale(v, &mut self.rgba);
+                &self.rgba
+              }
+              None => v,
+            };
+            self.format.convert(v, self.mapper.as_mut(), out);
+          }
+        }
+        let image = decoder.image();
+        self.info = Some(FrameInfo {
+          index: self.position,
+          delay: image.control.delay,
+          disposal: image.control.disposal,
+          rect: image.rect,
+          transparent: image.control.transparent,
+          local_palette: image.palette.is_some(),
+          interlaced: image.interlaced,
+        });
+        if self.position == self.timestamps.len() {
+          self.timestamps.push(self.elapsed);
+          self.elapsed += f64::from(image.control.delay) * 10_f64;
+        }
+        if let Some(mut checkpoint) = checkpoint {
+          keyframes.indexed += 1;
+          // Raw images do not depend on the earlier ones
+          if self.raw || decoder.is_keyframe() {
+            checkpoint.forget();
+            keyframes.checkpoints.push((self.position, checkpoint));
+          } else if checkpoint.size() > 0 || self.position == 0 {
+            keyframes.size += checkpoint.size();
+            keyframes.checkpoints.push((self.position, checkpoint));
+          }
+        }
+        Ok(true)
+      }
+      Frames::Ffmpeg(stdout) => match stdout.read_exact(match out {
+        Some(v) => v,
+        None => {
+          let size = self.width as usize * self.height as usize * self.format.bytes_per_pixel();
+          self.rgba.resize(size, 0);
+          &mut self.rgba
+        }
+      }) {
+        Ok(()) => Ok(true),
+        Err(v) if v.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(v) => Err(Cow::Owned(v.to_string())),
+      },
+    }
+  }
+
+  /// Moves on to the frame at `index`, resuming from the nearest
+  /// checkpoint ahead of it unless decoding on is closer
+  pub(crate) fn seek(&mut self, index: usize) -> Result<()> {
+    let Frames::Native(decoder) = &mut self.frames else {
+      return Err(Cow::Borrowed(
+        "Seeking is not supported by the ffmpeg backend",
+      ));
+    };
+    if self.frame_count.is_some_and(|v| index >= v) {
+      return Err(Cow::Borrowed("The frame index is out of range"));
+    }
+
+    let checkpoints = &self.keyframes.checkpoints;
+    let nearest = checkpoints
+      .partition_point(|v| v.0 <= index)
+      .checked_sub(1)
+      .map(|i| &checkpoints[i]);
+    if let Some((at, checkpoint)) = nearest {
+      if !(*at..=index).contains(&self.position) {
+        decoder.restore(checkpoint)?;
+        self.position = *at;
+      }
+    }
+    while self.position < index {
+      if !self.next_frame(None)? {
+        // Streams are only known to be too short once received whole
+        return Err(Cow::Borrowed(match self.frame_count {
+          Some(_) => "Unexpected end of GIF data",
+          None => "The frame index is out of range",
+        }));
+      }
+      self.position += 1;
+    }
+
+    if let Some(v) = &self.selection {
+      self.selected = v.count_before(index);
+    }
+    Ok(())
+  }
+
+  /// Reads the frame at `index` into `buf`, selected or not
+  pub(crate) fn read_at(&mut self, index: usize) -> Result<()> {
+    self.seek(index)?;
+    let mut buf = take(&mut self.buf);
+    let read = self.next_frame(Some(&mut buf));
+    self.buf = buf;
+    if !read? {
+      return Err(Cow::Borrowed("Unexpected end of GIF data"));
+    }
+    self.position += 1;
+    if self
+      .selection
+      .as_ref()
+      .is_some_and(|v| v.get(self.selected) == Some(index))
+    {
+      self.selected += 1;
+    }
+    Ok(())
+  }
 }
 
 impl ToNapi for &mut Extract {
@@ -35,32 +750,136 @@ impl ToNapi for &mut Extract {
   }
 }
 
-pub(crate) fn extract_native(probe: &Probe) -> Result<Extract> {
-  let args = [
-    "-i", &probe.src, // Input parameters
-    "-f", "rawvideo", "-pix_fmt", "rgba", "-", // Output parameters
-  ];
-  Ok(Extract {
-    stdout: Command::new("./bin/ffmpeg")
-      .args(args)
-      .stdin(Stdio::null())
+pub(crate) fn extract_native(probe: &Probe, options: &ExtractOptions) -> Result<Extract> {
+  let ranged = options.start.is_some() || options.end.is_some() || options.step.is_some();
+  if options.unit == RangeUnit::Ms && ranged && probe.known_delays().is_none() {
+    return Err(Cow::Borrowed(
+      "Millisecond ranges are not supported for streamed input",
+    ));
+  }
+  match options.backend {
+    Backend::Native => {
+      let input = probe
+        .open(false)
+        .map_err(|v| Cow::Owned(format!("Generic: {v}")))?;
+      let decoder = gif::Decoder::new(input)?;
+      let (width, height) = (
+        u32::from(decoder.screen.width),
+        u32::from(decoder.screen.height),
+      );
+      let scaler = options.scaling(width, height).map(|scaling| Scaler {
+        resizer: gif::Resizer::with_filter(
+          (width as usize, height as usize),
+          (scaling.resized.0 as usize, scaling.resized.1 as usize),
+          options.filter,
+        ),
+        scaling,
+        resized: Vec::new(),
+      });
+      let size = scaler.as_ref().map_or((width, height), |v| v.scaling.size);
+      let mapper = match (options.pixel_format, &decoder.screen.palette) {
+        (PixelFormat::Indexed, Some(colors)) => Some(
+          UserPalette {
+            colors: colors.clone(),
+            transparent: None,
+          }
+          .mapper(128, true),
+        ),
+        (PixelFormat::Indexed, None) => {
+          return Err(Cow::Borrowed(
+            "The indexed pixel format requires a global color table",
+          ))
+        }
+        _ => None,
+      };
+      Ok(Extract::new(
+        Frames::Native(Box::new(decoder)),
+        size,
+        scaler,
+        mapper,
+        probe,
+        options,
+      ))
+    }
+    Backend::Ffmpeg => extract_ffmpeg(probe, options),
+  }
+}
+
+fn extract_ffmpeg(probe: &Probe, options: &ExtractOptions) -> Result<Extract> {
+  if options.raw {
+    return Err(Cow::Borrowed(
+      "Raw frames are not supported by the ffmpeg backend",
+    ));
+  }
+  if options.metadata {
+    return Err(Cow::Borrowed(
+      "Frame metadata is not supported by the ffmpeg backend",
+    ));
+  }
+  #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+  let (width, height) = (probe.width as u32, probe.height as u32);
+  let scaling = options.scaling(width, height);
+  let filter = scaling.as_ref().map(|Scaling { resized, size }| {
+    format!(
+      "scale={w}:{h}:flags={flags},crop={cw}:{ch}",
+      w = resized.0,
+      h = resized.1,
+      flags = match options.filter {
+        gif::Filter::Lanczos => "lanczos",
+        gif::Filter::Bilinear => "bilinear",
+        gif::Filter::Nearest => "neighbor",
+      },
+      cw = size.0,
+      ch = size.1,
+    )
+  });
+  // Input parameters, piping in-memory and streamed data through stdin
+  let piped = !matches!(probe.source, Source::File);
+  let mut args = if piped {
+    vec!["-f", "gif", "-i", "pipe:0"]
+  } else {
+    vec!["-i", &probe.src]
+  };
+  if let Some(filter) = &filter {
+    args.extend(["-vf", filter]);
+  }
+  let pix_fmt = options.pixel_format.ffmpeg()?;
+  args.extend(["-f", "rawvideo", "-pix_fmt", pix_fmt, "-"]); // Output parameters
+  let spawn = |program: &str| {
+    Command::new(program)
+      .args(&args)
+      .stdin(if piped { Stdio::piped() } else { Stdio::null() })
       .stdout(Stdio::piped())
       .stderr(Stdio::null())
       .spawn()
-      .or(
-        Command::new("ffmpeg")
-          .args(args)
-          .stdin(Stdio::null())
-          .stdout(Stdio::piped())
-          .stderr(Stdio::piped())
-          .spawn(),
-      )
-      .map_err(|v| Cow::Owned(format!("Generic: {v}")))?
-      .stdout
-      .take()
-      .ok_or(Cow::Borrowed("Failed to extract output handle"))?,
-    buf: vec![0_u8; probe.width as usize * probe.height as usize * 4_usize],
-  })
+  };
+  let mut child = spawn("./bin/ffmpeg")
+    .or_else(|_| spawn("ffmpeg"))
+    .map_err(|v| Cow::Owned(format!("Generic: {v}")))?;
+  if let Some(mut stdin) = child.stdin.take() {
+    // Waits for the chunks on its own thread, as `ffmpeg` reads them
+    let mut input = probe
+      .open(true)
+      .map_err(|v| Cow::Owned(format!("Generic: {v}")))?;
+    // Fails once `ffmpeg` stops reading, which only happens when it exits
+    thread::Builder::new()
+      .name("easygif-ffmpeg-input".to_owned())
+      .spawn(move || io::copy(&mut input, &mut stdin))
+      .map_err(|_| Cow::Borrowed("Failed to spawn the input thread"))?;
+  }
+  Ok(Extract::new(
+    Frames::Ffmpeg(
+      child
+        .stdout
+        .take()
+        .ok_or(Cow::Borrowed("Failed to extract output handle"))?,
+    ),
+    scaling.map_or((width, height), |v| v.size),
+    None,
+    None,
+    probe,
+    options,
+  ))
 }
 
 extern "C" fn extract_finalize(_env: native::env, data: *mut c_void, _hint: *mut c_void) {
@@ -77,50 +896,578 @@ extern "C" fn extract_constructor(
   null_mut()
 }
 
+extern "C" fn extract_buffer_finalize(env: native::env, _data: *mut c_void, hint: *mut c_void) {
+  let _ = native::delete_reference(env, hint);
+}
+
+/// Hands the frame in `buf` over to JS. With `zeroCopy` the `Buffer` is a
+/// view over `buf` holding onto `owner`, so the instance is not collected
+/// while the view is alive
+fn frame_buffer(
+  env: native::env,
+  extract: &mut Extract,
+  owner: native::value,
+) -> Result<native::value> {
+  if !extract.zero_copy {
+    return native::create_buffer_copy(env, &extract.buf);
+  }
+  let owner = native::create_reference(env, owner)?;
+  native::create_external_buffer(env, &mut extract.buf, extract_buffer_finalize, owner).inspect_err(
+    |_| {
+      let _ = native::delete_reference(env, owner);
+    },
+  )
+}
+
+/// The value yielded for the frame in `buf`, along with its metadata with
+/// `ExtractOptions`.`metadata` set
+fn frame_value(
+  env: native::env,
+  extract: &mut Extract,
+  owner: native::value,
+) -> Result<native::value> {
+  if extract.raw {
+    return raw_value(env, extract);
+  }
+  let pixels = frame_buffer(env, extract, owner)?;
+  let Some(info) = extract.info.filter(|_| extract.metadata) else {
+    return Ok(pixels);
+  };
+  let result = info_value(env, extract, info)?;
+  native::set_named_property(env, result, "pixels", pixels)?;
+  Ok(result)
+}
+
+/// The last image as stored in the file, along with its metadata
+fn raw_value(env: native::env, extract: &Extract) -> Result<native::value> {
+  let (Frames::Native(decoder), Some(info)) = (&extract.frames, extract.info) else {
+    return Err(Cow::Borrowed("No image was read"));
+  };
+  let image = decoder.image();
+  let result = info_value(env, extract, info)?;
+  native::set_named_property(env, result, "interlaced", info.interlaced)?;
+  let palette = match image
+    .palette
+    .as_deref()
+    .or(decoder.screen.palette.as_deref())
+  {
+    Some(v) => native::create_buffer_copy(env, v)?,
+    None => native::null(env),
+  };
+  native::set_named_property(env, result, "palette", palette)?;
+  let indices = native::create_buffer_copy(env, &image.indices)?;
+  native::set_named_property(env, result, "indices", indices)?;
+  Ok(result)
+}
+
+/// The metadata of a frame, see `FrameInfo`
+fn info_value(env: native::env, extract: &Extract, info: FrameInfo) -> Result<native::value> {
+  let rect = native::create_object(env)?;
+  native::set_named_property(env, rect, "left", f64::from(info.rect.left))?;
+  native::set_named_property(env, rect, "top", f64::from(info.rect.top))?;
+  native::set_named_property(env, rect, "width", f64::from(info.rect.width))?;
+  native::set_named_property(env, rect, "height", f64::from(info.rect.height))?;
+
+  let result = native::create_object(env)?;
+  native::set_named_property(env, result, "index", info.index)?;
+  let timestamp = extract.timestamps.get(info.index).copied();
+  native::set_named_property(env, result, "timestampMs", timestamp.unwrap_or_default())?;
+  native::set_named_property(env, result, "delayMs", f64::from(info.delay) * 10_f64)?;
+  native::set_named_property(env, result, "disposal", info.disposal.as_str())?;
+  native::set_named_property(env, result, "rect", rect)?;
+  let transparent = match info.transparent {
+    Some(v) => f64::from(v).to_napi(env)?,
+    None => native::null(env),
+  };
+  native::set_named_property(env, result, "transparentIndex", transparent)?;
+  native::set_named_property(env, result, "localPalette", info.local_palette)?;
+  Ok(result)
+}
+
+fn extract_result(
+  env: native::env,
+  extract: &mut Extract,
+  read: bool,
+  owner: native::value,
+) -> Result<native::value> {
+  let result = native::create_object(env)?;
+  let value = if read {
+    frame_value(env, extract, owner)?
+  } else {
+    native::undefined(env)
+  };
+  native::set_named_property(env, result, "value", value)?;
+  native::set_named_property(env, result, "done", !read)?;
+  Ok(result)
+}
+
+/// Creates an iterator object holding onto the `Extract` instance, so the
+/// instance is not collected while being iterated
+fn extract_iterator(
+  env: native::env,
+  this: native::value,
+  next: native::callback,
+) -> Result<native::value> {
+  let inner = native::unwrap(env, this)?;
+  let object = native::create_object(env)?;
+  native::set_named_property(env, object, "next", native::Callback("next", inner, next))?;
+  native::set_named_property(env, object, "extract", this)?;
+  Ok(object)
+}
+
 extern "C" fn extract_next(env: native::env, info: native::callback_info) -> native::value {
-  let (_, _, data) = native::unwrap_throw!(env, native::get_cb_info(env, info));
+  let (this, _, data) = native::unwrap_throw!(env, native::get_cb_info(env, info));
   let extract = unsafe { &mut *(data as *mut Extract) };
-  let result = native::unwrap_throw!(env, native::create_object(env));
-  native::unwrap_throw!(
-    env,
-    native::set_named_property(env, result, "value", native::undefined(env))
-  );
-  native::unwrap_throw!(env, native::set_named_property(env, result, "done", false));
-  if let Err(v) = extract.stdout.read_exact(&mut extract.buf) {
-    if let ErrorKind::UnexpectedEof = v.kind() {
-      native::unwrap_throw!(env, native::set_named_property(env, result, "done", true));
-      return result;
-    }
-    native::throw_error(env, &v.to_string(), &v.to_string());
-    null_mut()
-  } else {
-    native::unwrap_throw!(
+  if extract.reading {
+    let msg = "A frame read is already in progress";
+    native::throw_error(env, msg, msg);
+    return null_mut();
+  }
+  if extract.stream.as_ref().is_some_and(|v| !v.is_ended()) {
+    // Waiting would block the main thread the chunks are received on
+    let msg = "Streamed input can only be iterated asynchronously until it is fully received";
+    native::throw_error(env, msg, msg);
+    return null_mut();
+  }
+  let read = native::unwrap_throw!(env, extract.read_frame());
+  native::unwrap_throw!(env, extract_result(env, extract, read, this))
+}
+
+extern "C" fn extract_iter(env: native::env, info: native::callback_info) -> native::value {
+  let (this, _, _) = native::unwrap_throw!(env, native::get_cb_info(env, info));
+  native::unwrap_throw!(env, extract_iterator(env, this, extract_next))
+}
+
+struct ExtractNextContext {
+  extract: &'static mut Extract,
+  iterator: native::nref,
+  async_work: native::async_work,
+  deferred: native::deferred,
+  result: Result<bool>,
+}
+
+extern "C" fn extract_next_execute(_env: native::env, data: *mut c_void) {
+  let ctx = unsafe { &mut *data.cast::<ExtractNextContext>() };
+  ctx.result = ctx.extract.read_frame();
+}
+
+extern "C" fn extract_next_complete(env: native::env, _status: native::status, data: *mut c_void) {
+  let ctx = unsafe { &mut *data.cast::<ExtractNextContext>() };
+  ctx.extract.reading = false;
+  let _ = match &ctx.result {
+    Ok(v) => native::get_reference_value(env, ctx.iterator)
+      .and_then(|owner| extract_result(env, ctx.extract, *v, owner))
+      .and_then(|v| native::resolve_deferred(env, ctx.deferred, v)),
+    Err(v) => {
+      native::create_string_utf8(env, v).and_then(|v| native::reject_deferred(env, ctx.deferred, v))
+    }
+  };
+
+  let _ = native::delete_reference(env, ctx.iterator);
+  let _ = native::delete_async_work(env, ctx.async_work);
+  unsafe { drop_in_place(data) };
+  unsafe {
+    dealloc(data.cast(), Layout::new::<ExtractNextContext>());
+  };
+}
+
+/// Queues the read once the frame it needs is received
+fn extract_next_queue(env: native::env, data: *mut ExtractNextContext) -> Result<()> {
+  let ctx = unsafe { &mut *data };
+  if let Some((stream, until)) = ctx.extract.awaiting(ctx.extract.next_index()) {
+    let notifier = native::Notifier::new(
       env,
-      native::set_named_property(
+      "Wait for the next streamed GIF frame",
+      data.cast(),
+      extract_next_received,
+    )?;
+    stream.notify(until, notifier);
+    return Ok(());
+  }
+  ctx.async_work = native::create_async_work(
+    env,
+    "Read the next GIF frame",
+    extract_next_execute,
+    extract_next_complete,
+    data.cast(),
+  )
+  .map_err(|_| Cow::Borrowed("Failed to create an async_work"))?;
+  native::queue_async_work(env, ctx.async_work)
+    .map_err(|_| Cow::Borrowed("Failed to queue the async_work"))?;
+  Ok(())
+}
+
+extern "C" fn extract_next_received(
+  env: native::env,
+  _js_callback: native::value,
+  context: *mut c_void,
+  _data: *mut c_void,
+) {
+  if env.is_null() {
+    unsafe { drop_in_place(context.cast::<ExtractNextContext>()) };
+    unsafe {
+      dealloc(context.cast(), Layout::new::<ExtractNextContext>());
+    };
+    return;
+  }
+  if let Err(v) = extract_next_queue(env, context.cast()) {
+    unsafe { &mut *context.cast::<ExtractNextContext>() }.result = Err(v);
+    extract_next_complete(env, native::status::ok, context);
+  }
+}
+
+fn extract_next_promise(
+  env: native::env,
+  info: native::callback_info,
+  deferred: native::deferred,
+) -> Result<()> {
+  let (this, _, data) = native::get_cb_info(env, info)?;
+  let extract = unsafe { &mut *(data as *mut Extract) };
+  if extract.reading {
+    return Err(Cow::Borrowed("A frame read is already in progress"));
+  }
+
+  let result = Box::leak(Box::new(ExtractNextContext {
+    extract,
+    iterator: native::create_reference(env, this)?,
+    async_work: null_mut(),
+    deferred,
+    result: Ok(false),
+  }));
+  extract_next_queue(env, result)?;
+  result.extract.reading = true;
+  Ok(())
+}
+
+extern "C" fn extract_async_next(env: native::env, info: native::callback_info) -> native::value {
+  let (promise, deferred) = native::unwrap_throw!(env, native::create_promise(env));
+
+  if !deferred.is_null() {
+    if let Err(v) = extract_next_promise(env, info, deferred) {
+      native::unwrap_throw!(
         env,
-        result,
-        "value",
-        native::unwrap_throw!(env, native::create_buffer_copy(env, &extract.buf))
-      )
-    );
-    result
+        native::create_string_utf8(env, &v).and_then(|v| native::reject_deferred(env, deferred, v))
+      );
+    }
   }
+
+  promise
 }
 
-extern "C" fn extract_iter(env: native::env, info: native::callback_info) -> native::value {
+extern "C" fn extract_async_iter(env: native::env, info: native::callback_info) -> native::value {
   let (this, _, _) = native::unwrap_throw!(env, native::get_cb_info(env, info));
-  let inner = native::unwrap_throw!(env, native::unwrap(env, this));
-  let object = native::unwrap_throw!(env, native::create_object(env));
-  native::unwrap_throw!(
+  native::unwrap_throw!(env, extract_iterator(env, this, extract_async_next))
+}
+
+extern "C" fn extract_width(env: native::env, info: native::callback_info) -> native::value {
+  let (this, _, _) = native::unwrap_throw!(env, native::get_cb_info(env, info));
+  let extract =
+    unsafe { &mut *(native::unwrap_throw!(env, native::unwrap(env, this)) as *mut Extract) };
+  native::unwrap_throw!(env, f64::from(extract.width).to_napi(env))
+}
+
+extern "C" fn extract_height(env: native::env, info: native::callback_info) -> native::value {
+  let (this, _, _) = native::unwrap_throw!(env, native::get_cb_info(env, info));
+  let extract =
+    unsafe { &mut *(native::unwrap_throw!(env, native::unwrap(env, this)) as *mut Extract) };
+  native::unwrap_throw!(env, f64::from(extract.height).to_napi(env))
+}
+
+extern "C" fn extract_palette(env: native::env, info: native::callback_info) -> native::value {
+  let (this, _, _) = native::unwrap_throw!(env, native::get_cb_info(env, info));
+  let extract =
+    unsafe { &mut *(native::unwrap_throw!(env, native::unwrap(env, this)) as *mut Extract) };
+  match &extract.mapper {
+    Some(v) => native::unwrap_throw!(env, palette_to_napi(env, v.palette(), v.transparent())),
+    None => native::null(env),
+  }
+}
+
+struct ExtractSeekContext {
+  extract: &'static mut Extract,
+  this: native::nref,
+  index: usize,
+  /// Set for `frameAt`, which resolves with the frame
+  read: bool,
+  async_work: native::async_work,
+  deferred: native::deferred,
+  result: Result<()>,
+}
+
+extern "C" fn extract_seek_execute(_env: native::env, data: *mut c_void) {
+  let ctx = unsafe { &mut *data.cast::<ExtractSeekContext>() };
+  ctx.result = if ctx.read {
+    ctx.extract.read_at(ctx.index)
+  } else {
+    ctx.extract.seek(ctx.index)
+  };
+}
+
+extern "C" fn extract_seek_complete(env: native::env, _status: native::status, data: *mut c_void) {
+  let ctx = unsafe { &mut *data.cast::<ExtractSeekContext>() };
+  ctx.extract.reading = false;
+  let _ = match &ctx.result {
+    Ok(()) if ctx.read => native::get_reference_value(env, ctx.this)
+      .and_then(|owner| frame_value(env, ctx.extract, owner))
+      .and_then(|v| native::resolve_deferred(env, ctx.deferred, v)),
+    Ok(()) => native::resolve_deferred(env, ctx.deferred, native::undefined(env)),
+    Err(v) => {
+      native::create_string_utf8(env, v).and_then(|v| native::reject_deferred(env, ctx.deferred, v))
+    }
+  };
+
+  let _ = native::delete_reference(env, ctx.this);
+  let _ = native::delete_async_work(env, ctx.async_work);
+  unsafe { drop_in_place(data) };
+  unsafe {
+    dealloc(data.cast(), Layout::new::<ExtractSeekContext>());
+  };
+}
+
+/// Queues the seek once the frame it needs is received
+fn extract_seek_queue(env: native::env, data: *mut ExtractSeekContext) -> Result<()> {
+  let ctx = unsafe { &mut *data };
+  if let Some((stream, until)) = ctx.extract.awaiting(Some(ctx.index)) {
+    let notifier = native::Notifier::new(
+      env,
+      "Wait for a streamed GIF frame",
+      data.cast(),
+      extract_seek_received,
+    )?;
+    stream.notify(until, notifier);
+    return Ok(());
+  }
+  ctx.async_work = native::create_async_work(
     env,
-    native::set_named_property(
+    "Seek a GIF frame",
+    extract_seek_execute,
+    extract_seek_complete,
+    data.cast(),
+  )
+  .map_err(|_| Cow::Borrowed("Failed to create an async_work"))?;
+  native::queue_async_work(env, ctx.async_work)
+    .map_err(|_| Cow::Borrowed("Failed to queue the async_work"))?;
+  Ok(())
+}
+
+extern "C" fn extract_seek_received(
+  env: native::env,
+  _js_callback: native::value,
+  context: *mut c_void,
+  _data: *mut c_void,
+) {
+  if env.is_null() {
+    unsafe { drop_in_place(context.cast::<ExtractSeekContext>()) };
+    unsafe {
+      dealloc(context.cast(), Layout::new::<ExtractSeekContext>());
+    };
+    return;
+  }
+  if let Err(v) = extract_seek_queue(env, context.cast()) {
+    unsafe { &mut *context.cast::<ExtractSeekContext>() }.result = Err(v);
+    extract_seek_complete(env, native::status::ok, context);
+  }
+}
+
+fn extract_seek_promise(
+  env: native::env,
+  info: native::callback_info,
+  deferred: native::deferred,
+  read: bool,
+) -> Result<()> {
+  let (this, args, _) = native::get_cb_info(env, info)?;
+  let extract = unsafe { &mut *(native::unwrap(env, this)? as *mut Extract) };
+  #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+  let index = match args.first() {
+    Some(&v) if matches!(native::value_type(env, v)?, native::valuetype::Number) => {
+      match native::get_value_double(env, v)? {
+        v if v.fract() == 0_f64 && v >= 0_f64 => v as usize,
+        _ => return Err(Cow::Borrowed("Invalid frame index")),
+      }
+    }
+    _ => return Err(Cow::Borrowed("Function call expects a frame index")),
+  };
+  if extract.reading {
+    return Err(Cow::Borrowed("A frame read is already in progress"));
+  }
+
+  let result = Box::leak(Box::new(ExtractSeekContext {
+    extract,
+    this: native::create_reference(env, this)?,
+    index,
+    read,
+    async_work: null_mut(),
+    deferred,
+    result: Ok(()),
+  }));
+  extract_seek_queue(env, result)?;
+  result.extract.reading = true;
+  Ok(())
+}
+
+fn extract_seek_call(env: native::env, info: native::callback_info, read: bool) -> native::value {
+  let (promise, deferred) = native::unwrap_throw!(env, native::create_promise(env));
+
+  if !deferred.is_null() {
+    if let Err(v) = extract_seek_promise(env, info, deferred, read) {
+      native::unwrap_throw!(
+        env,
+        native::create_string_utf8(env, &v).and_then(|v| native::reject_deferred(env, deferred, v))
+      );
+    }
+  }
+
+  promise
+}
+
+extern "C" fn extract_seek(env: native::env, info: native::callback_info) -> native::value {
+  extract_seek_call(env, info, false)
+}
+
+extern "C" fn extract_frame_at(env: native::env, info: native::callback_info) -> native::value {
+  extract_seek_call(env, info, true)
+}
+
+struct ExtractReadContext {
+  extract: &'static mut Extract,
+  this: native::nref,
+  /// Keeps `out` alive
+  buffer: native::nref,
+  out: &'static mut [u8],
+  async_work: native::async_work,
+  deferred: native::deferred,
+  result: Result<bool>,
+}
+
+extern "C" fn extract_read_execute(_env: native::env, data: *mut c_void) {
+  let ctx = unsafe { &mut *data.cast::<ExtractReadContext>() };
+  ctx.result = ctx.extract.read_frame_into(ctx.out);
+}
+
+extern "C" fn extract_read_complete(env: native::env, _status: native::status, data: *mut c_void) {
+  let ctx = unsafe { &mut *data.cast::<ExtractReadContext>() };
+  ctx.extract.reading = false;
+  let _ = match &ctx.result {
+    Ok(v) => { *v }
+      .to_napi(env)
+      .and_then(|v| native::resolve_deferred(env, ctx.deferred, v)),
+    Err(v) => {
+      native::create_string_utf8(env, v).and_then(|v| native::reject_deferred(env, ctx.deferred, v))
+    }
+  };
+
+  let _ = native::delete_reference(env, ctx.buffer);
+  let _ = native::delete_reference(env, ctx.this);
+  let _ = native::delete_async_work(env, ctx.async_work);
+  unsafe { drop_in_place(data) };
+  unsafe {
+    dealloc(data.cast(), Layout::new::<ExtractReadContext>());
+  };
+}
+
+/// Queues the read once the frame it needs is received
+fn extract_read_queue(env: native::env, data: *mut ExtractReadContext) -> Result<()> {
+  let ctx = unsafe { &mut *data };
+  if let Some((stream, until)) = ctx.extract.awaiting(ctx.extract.next_index()) {
+    let notifier = native::Notifier::new(
       env,
-      object,
-      "next",
-      native::Callback("next", inner, extract_next)
-    )
-  );
-  object
+      "Wait for the next streamed GIF frame",
+      data.cast(),
+      extract_read_received,
+    )?;
+    stream.notify(until, notifier);
+    return Ok(());
+  }
+  ctx.async_work = native::create_async_work(
+    env,
+    "Read the next GIF frame into a buffer",
+    extract_read_execute,
+    extract_read_complete,
+    data.cast(),
+  )
+  .map_err(|_| Cow::Borrowed("Failed to create an async_work"))?;
+  native::queue_async_work(env, ctx.async_work)
+    .map_err(|_| Cow::Borrowed("Failed to queue the async_work"))?;
+  Ok(())
+}
+
+extern "C" fn extract_read_received(
+  env: native::env,
+  _js_callback: native::value,
+  context: *mut c_void,
+  _data: *mut c_void,
+) {
+  if env.is_null() {
+    unsafe { drop_in_place(context.cast::<ExtractReadContext>()) };
+    unsafe {
+      dealloc(context.cast(), Layout::new::<ExtractReadContext>());
+    };
+    return;
+  }
+  if let Err(v) = extract_read_queue(env, context.cast()) {
+    unsafe { &mut *context.cast::<ExtractReadContext>() }.result = Err(v);
+    extract_read_complete(env, native::status::ok, context);
+  }
+}
+
+fn extract_read_promise(
+  env: native::env,
+  info: native::callback_info,
+  deferred: native::deferred,
+) -> Result<()> {
+  let (this, args, _) = native::get_cb_info(env, info)?;
+  let extract = unsafe { &mut *(native::unwrap(env, this)? as *mut Extract) };
+
+  let v = args
+    .into_iter()
+    .next()
+    .ok_or(Cow::Borrowed("Function call expects exactly one argument"))?;
+  if !native::is_buffer(env, v)? {
+    return Err(Cow::Borrowed(
+      "The first argument is expected to be of type `Buffer`",
+    ));
+  }
+  if extract.raw {
+    return Err(Cow::Borrowed("Raw frames cannot be read into a buffer"));
+  }
+  let out = native::get_buffer_info_mut(env, v)?;
+  if out.len() != extract.buf.len() {
+    return Err(Cow::Owned(format!(
+      "The buffer is expected to be of size {buf_size}",
+      buf_size = extract.buf.len(),
+    )));
+  }
+  if extract.reading {
+    return Err(Cow::Borrowed("A frame read is already in progress"));
+  }
+
+  let result = Box::leak(Box::new(ExtractReadContext {
+    extract,
+    this: native::create_reference(env, this)?,
+    buffer: native::create_reference(env, v)?,
+    out,
+    async_work: null_mut(),
+    deferred,
+    result: Ok(false),
+  }));
+  extract_read_queue(env, result)?;
+  result.extract.reading = true;
+  Ok(())
+}
+
+extern "C" fn extract_read_into(env: native::env, info: native::callback_info) -> native::value {
+  let (promise, deferred) = native::unwrap_throw!(env, native::create_promise(env));
+
+  if !deferred.is_null() {
+    if let Err(v) = extract_read_promise(env, info, deferred) {
+      native::unwrap_throw!(
+        env,
+        native::create_string_utf8(env, &v).and_then(|v| native::reject_deferred(env, deferred, v))
+      );
+    }
+  }
+
+  promise
 }
 
 pub(crate) fn extract_struct(env: native::env) -> Result<native::value> {
@@ -129,21 +1476,94 @@ pub(crate) fn extract_struct(env: native::env) -> Result<native::value> {
     let global = native::global(env);
     let symbol = native::get_named_property(env, global, "Symbol")?;
     let symbol_iterator = native::get_named_property(env, symbol, "iterator")?;
+    let symbol_async_iterator = native::get_named_property(env, symbol, "asyncIterator")?;
     let extract_struct = native::define_class(
       env,
       "Extract",
       extract_constructor,
       null_mut(),
-      &[native::property_descriptor {
-        utf8name: null(),
-        name: symbol_iterator,
-        method: Some(extract_iter),
-        getter: None,
-        setter: None,
-        value: null_mut(),
-        attributes: native::property_attribute::Enumerable,
-        data: null_mut(),
-      }],
+      &[
+        native::property_descriptor {
+          utf8name: null(),
+          name: symbol_iterator,
+          method: Some(extract_iter),
+          getter: None,
+          setter: None,
+          value: null_mut(),
+          attributes: native::property_attribute::Enumerable,
+          data: null_mut(),
+        },
+        native::property_descriptor {
+          utf8name: null(),
+          name: symbol_async_iterator,
+          method: Some(extract_async_iter),
+          getter: None,
+          setter: None,
+          value: null_mut(),
+          attributes: native::property_attribute::Enumerable,
+          data: null_mut(),
+        },
+        native::property_descriptor {
+          utf8name: null_mut(),
+          name: native::create_string_utf8(env, "width")?,
+          method: None,
+          getter: Some(extract_width),
+          setter: None,
+          value: null_mut(),
+          attributes: native::property_attribute::Enumerable,
+          data: null_mut(),
+        },
+        native::property_descriptor {
+          utf8name: null_mut(),
+          name: native::create_string_utf8(env, "height")?,
+          method: None,
+          getter: Some(extract_height),
+          setter: None,
+          value: null_mut(),
+          attributes: native::property_attribute::Enumerable,
+          data: null_mut(),
+        },
+        native::property_descriptor {
+          utf8name: null_mut(),
+          name: native::create_string_utf8(env, "palette")?,
+          method: None,
+          getter: Some(extract_palette),
+          setter: None,
+          value: null_mut(),
+          attributes: native::property_attribute::Enumerable,
+          data: null_mut(),
+        },
+        native::property_descriptor {
+          utf8name: null_mut(),
+          name: native::create_string_utf8(env, "seek")?,
+          method: Some(extract_seek),
+          getter: None,
+          setter: None,
+          value: null_mut(),
+          attributes: native::property_attribute::Enumerable,
+          data: null_mut(),
+        },
+        native::property_descriptor {
+          utf8name: null_mut(),
+          name: native::create_string_utf8(env, "frameAt")?,
+          method: Some(extract_frame_at),
+          getter: None,
+          setter: None,
+          value: null_mut(),
+          attributes: native::property_attribute::Enumerable,
+          data: null_mut(),
+        },
+        native::property_descriptor {
+          utf8name: null_mut(),
+          name: native::create_string_utf8(env, "readInto")?,
+          method: Some(extract_read_into),
+          getter: None,
+          setter: None,
+          value: null_mut(),
+          attributes: native::property_attribute::Enumerable,
+          data: null_mut(),
+        },
+      ],
     )?;
     unsafe { EXTRACT_STRUCT = native::create_reference(env, extract_struct)? };
   }
@@ -152,6 +1572,7 @@ pub(crate) fn extract_struct(env: native::env) -> Result<native::value> {
 
 struct ExtractContext {
   probe: &'static mut Probe,
+  options: ExtractOptions,
   async_work: native::async_work,
   deferred: native::deferred,
   result: Result<&'static mut Extract>,
@@ -159,7 +1580,9 @@ struct ExtractContext {
 
 extern "C" fn extract_execute(_env: native::env, data: *mut c_void) {
   let ctx = unsafe { &mut *data.cast::<ExtractContext>() };
-  ctx.result = extract_native(ctx.probe).map(Box::new).map(Box::leak);
+  ctx.result = extract_native(ctx.probe, &ctx.options)
+    .map(Box::new)
+    .map(Box::leak);
 }
 
 extern "C" fn extract_complete(env: native::env, _status: native::status, data: *mut c_void) {
@@ -194,9 +1617,14 @@ fn extract_promise(
       <&mut Probe>::from_napi(env, v)
         .map_err(|_| Cow::Borrowed("The first argument is expected to be of type `Probe`"))
     })?;
+  let options = match args.next() {
+    Some(v) => ExtractOptions::from_napi(env, v)?,
+    None => ExtractOptions::default(),
+  };
 
   let result = Box::leak(Box::new(ExtractContext {
     probe,
+    options,
     async_work: null_mut(),
     deferred,
     result: Err(Cow::Borrowed("")),
@@ -228,3 +1656,132 @@ pub(crate) extern "C" fn extract(env: native::env, info: native::callback_info)
 
   promise
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{components::probe_native, gif::noise};
+
+  const WIDTH: u16 = 16;
+  const HEIGHT: u16 = 12;
+  const FRAMES: usize = 44;
+
+  /// Partial frames with transparent pixels, cycling through the disposals,
+  /// along with the opaque full frames 0 and 36. The composited snapshots
+  /// at 16 and 32 follow frames disposed to the background and to the
+  /// previous state respectively
+  fn animation() -> Vec<u8> {
+    let palette = noise(16 * 3, 1);
+    let mut encoder = gif::Encoder::new(Vec::new(), WIDTH, HEIGHT, Some(&palette), None).unwrap();
+    for i in 0..FRAMES {
+      let seed = u32::try_from(i).unwrap() + 2;
+      let full = i == 0 || i == 36;
+      let rect = if full {
+        gif::Rect {
+          left: 0,
+          top: 0,
+          width: WIDTH,
+          height: HEIGHT,
+        }
+      } else {
+        let [left, top, width, height] = noise(4, seed)[..] else {
+          unreachable!()
+        };
+        let (left, top) = (u16::from(left) % (WIDTH - 1), u16::from(top) % (HEIGHT - 1));
+        gif::Rect {
+          left,
+          top,
+          width: 1 + u16::from(width) % (WIDTH - left),
+          height: 1 + u16::from(height) % (HEIGHT - top),
+        }
+      };
+      let len = usize::from(rect.width) * usize::from(rect.height);
+      let indices = noise(len, seed * 7)
+        .into_iter()
+        .map(|v| if full { 1 + v % 15 } else { v % 16 })
+        .collect::<Vec<_>>();
+      encoder
+        .write_frame(&gif::Frame {
+          rect,
+          delay: 4,
+          disposal: [
+            gif::Disposal::Background,
+            gif::Disposal::Previous,
+            gif::Disposal::Keep,
+          ][i % 3],
+          transparent: Some(0),
+          palette: None,
+          indices: &indices,
+        })
+        .unwrap();
+    }
+    encoder.finish().unwrap()
+  }
+
+  fn open(data: &[u8]) -> Extract {
+    let probe = probe_native("buffer:0".to_owned(), Source::Memory(Arc::from(data))).unwrap();
+    extract_native(&probe, &ExtractOptions::default()).unwrap()
+  }
+
+  /// Every frame, decoded one after the other
+  fn sequential(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut extract = open(data);
+    let mut frames = Vec::new();
+    while extract.read_frame().unwrap() {
+      frames.push(extract.buf.clone());
+    }
+    assert_eq!(frames.len(), FRAMES);
+    frames
+  }
+
+  /// Reads frames out of order, each followed by the next one in sequence
+  fn assert_seeks(extract: &mut Extract, frames: &[Vec<u8>]) {
+    for index in [25, 3, 43, 17, 16, 15, 31, 32, 33, 9, 11, 0, 37, 36, 35, 21] {
+      extract.read_at(index).unwrap();
+      assert!(extract.buf == frames[index], "frame {index}");
+      if index + 1 < FRAMES {
+        assert!(extract.read_frame().unwrap());
+        assert!(extract.buf == frames[index + 1], "frame {index} + 1");
+      }
+    }
+    for index in [41, 2, 18] {
+      extract.seek(index).unwrap();
+      assert!(extract.read_frame().unwrap());
+      assert!(extract.buf == frames[index], "seeked frame {index}");
+    }
+  }
+
+  #[test]
+  fn seeks_to_the_sequentially_decoded_frames() {
+    let data = animation();
+    let frames = sequential(&data);
+    let mut extract = open(&data);
+    assert_seeks(&mut extract, &frames);
+
+    let checkpoints = &extract.keyframes.checkpoints;
+    assert_eq!(
+      checkpoints
+        .iter()
+        .map(|v| (v.0, v.1.size() > 0))
+        .collect::<Vec<_>>(),
+      [(0, false), (16, true), (32, true), (36, false)]
+    );
+    assert_eq!(
+      extract.seek(FRAMES).unwrap_err(),
+      "The frame index is out of range"
+    );
+  }
+
+  #[test]
+  fn seeks_from_keyframes_once_over_the_snapshot_budget() {
+    let data = animation();
+    let frames = sequential(&data);
+    let mut extract = open(&data);
+    extract.keyframes.size = SNAPSHOT_BUDGET;
+    assert_seeks(&mut extract, &frames);
+
+    let checkpoints = &extract.keyframes.checkpoints;
+    assert!(checkpoints.iter().all(|v| v.1.size() == 0));
+    assert_eq!(checkpoints.iter().map(|v| v.0).collect::<Vec<_>>(), [0, 36]);
+  }
+}
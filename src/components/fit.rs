@@ -0,0 +1,234 @@
+use std::{borrow::Cow, io::Write};
+
+use crate::Result;
+
+use super::{CombineOptions, NativeCombine, Output, Sink, WriteOptions};
+
+/// Colors are not reduced any further than this
+const MIN_COLORS: u16 = 16;
+/// Frames are not dropped any further than keeping every n-th one
+const MAX_FRAME_STEP: usize = 4;
+/// Frames are not scaled down further than this side (px)
+const MIN_SIDE: f64 = 16_f64;
+
+/// Settings `CombineOptions`.`maxBytes` settled on
+pub(crate) struct FitSettings {
+  pub(crate) width: u16,
+  pub(crate) height: u16,
+  pub(crate) max_colors: u16,
+  /// Every n-th frame is kept, displayed for the whole group
+  pub(crate) frame_step: usize,
+}
+
+struct FitFrame {
+  /// As written, in the `CombineOptions`.`pixelFormat` layout
+  pixels: Vec<u8>,
+  /// Display duration in milliseconds
+  duration: f64,
+}
+
+/// Keeps the written frames, so they can be encoded over again until the
+/// output fits
+pub(crate) struct Fitting {
+  options: CombineOptions,
+  max_bytes: u64,
+  /// The requested output size
+  size: (u16, u16),
+  sink: Sink,
+  frames: Vec<FitFrame>,
+}
+
+impl Fitting {
+  pub(crate) fn new(options: &CombineOptions, max_bytes: u64, sink: Sink) -> Result<Self> {
+    let scale = options.scale.unwrap_or((options.width, options.height));
+    let (Ok(width), Ok(height)) = (u16::try_from(scale.0), u16::try_from(scale.1)) else {
+      return Err(Cow::Borrowed("The output size exceeds the GIF limits"));
+    };
+    Ok(Self {
+      options: options.clone(),
+      max_bytes,
+      size: (width, height),
+      sink,
+      frames: Vec::new(),
+    })
+  }
+
+  pub(crate) fn write(&mut self, buf: &[u8], options: &WriteOptions) {
+    self.frames.push(FitFrame {
+      pixels: buf.to_vec(),
+      duration: options.delay_ms.unwrap_or(1000_f64 / self.options.fps),
+    });
+  }
+
+  fn encode(&self, settings: &FitSettings) -> Result<Vec<u8>> {
+    let mut options = self.options.clone();
+    options.scale = Some((u32::from(settings.width), u32::from(settings.height)));
+    options.max_colors = settings.max_colors;
+    let mut encoder = NativeCombine::create(&options, Sink::Memory(Vec::new()))?;
+    for group in self.frames.chunks(settings.frame_step) {
+      encoder.write(
+        &group[0].pixels,
+        &WriteOptions {
+          delay_ms: Some(group.iter().map(|v| v.duration).sum()),
+        },
+      )?;
+    }
+    match encoder.finish()? {
+      Output::Buffer(v) => Ok(v),
+      _ => Err(Cow::Borrowed("Unexpected output of the fitting encoder")),
+    }
+  }
+
+  /// Discards the written frames and the output
+  pub(crate) fn abort(self) {
+    self.sink.abort();
+  }
+
+  /// Reduces the colors first, then the size down to a half, then the frame
+  /// rate down to a quarter and finally the size again, until the output
+  /// fits
+  pub(crate) fn finish(mut self) -> Result<Output> {
+    let min_colors = self.options.max_colors.min(MIN_COLORS);
+    let min_side = f64::from(self.size.0.min(self.size.1));
+    let mut scale = 1_f64;
+    let mut settings = FitSettings {
+      width: self.size.0,
+      height: self.size.1,
+      max_colors: self.options.max_colors,
+      frame_step: 1,
+    };
+    loop {
+      let gif = self.encode(&settings)?;
+      if gif.len() as u64 <= self.max_bytes {
+        self
+          .sink
+          .write_all(&gif)
+          .map_err(|v| Cow::Owned(v.to_string()))?;
+        let output = self.sink.finish().map_err(|v| Cow::Owned(v.to_string()))?;
+        return Ok(Output::Fitted {
+          output: Box::new(output),
+          settings,
+        });
+      }
+
+      #[allow(clippy::cast_precision_loss)]
+      let ratio = self.max_bytes as f64 / gif.len() as f64;
+      let droppable = settings.frame_step < MAX_FRAME_STEP.min(self.frames.len());
+      let floor = (MIN_SIDE / min_side)
+        .max(if droppable { 0.5_f64 } else { 0_f64 })
+        .min(1_f64);
+      if self.options.palette.is_none() && settings.max_colors > min_colors {
+        settings.max_colors = (settings.max_colors / 2).max(min_colors);
+      } else if scale > floor {
+        // The size goes roughly with the area, within 50% to 90% a step
+        scale = (scale * ratio.sqrt().clamp(0.5_f64, 0.9_f64)).max(floor);
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let resize = |v: u16| ((f64::from(v) * scale).round() as u16).max(1);
+        settings.width = resize(self.size.0);
+        settings.height = resize(self.size.1);
+      } else if droppable {
+        settings.frame_step += 1;
+      } else {
+        return Err(Cow::Borrowed(
+          "The output does not fit within `CombineOptions`.`maxBytes`",
+        ));
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    components::PixelFormat,
+    gif::{self, noise},
+  };
+
+  const FRAMES: usize = 6;
+
+  fn options() -> CombineOptions {
+    CombineOptions {
+      width: 64,
+      height: 48,
+      fps: 10_f64,
+      max_colors: 256,
+      alpha_threshold: 128,
+      optimize: true,
+      // Not RGBA, so the frames are checked to be re-encoded as written
+      pixel_format: PixelFormat::Rgb24,
+      ..CombineOptions::default()
+    }
+  }
+
+  /// Encodes frames of noise, which compress poorly, within `max_bytes`
+  fn fit(max_bytes: u64) -> Result<(Vec<u8>, FitSettings)> {
+    let mut fitting = Fitting::new(&options(), max_bytes, Sink::Memory(Vec::new()))?;
+    for i in 0..FRAMES {
+      let pixels = noise(64 * 48 * 3, u32::try_from(i).unwrap() + 1);
+      fitting.write(&pixels, &WriteOptions::default());
+    }
+    match fitting.finish()? {
+      Output::Fitted { output, settings } => match *output {
+        Output::Buffer(v) => Ok((v, settings)),
+        _ => panic!("Expected an in-memory output"),
+      },
+      _ => panic!("Expected a fitted output"),
+    }
+  }
+
+  #[test]
+  fn shrinks_until_the_output_fits() {
+    let (full, settings) = fit(u64::MAX).unwrap();
+    assert_eq!(
+      (settings.width, settings.height, settings.max_colors),
+      (64, 48, 256)
+    );
+    assert_eq!(settings.frame_step, 1);
+
+    let mut steps = Vec::new();
+    for divisor in [2, 8, 32] {
+      let max_bytes = full.len() as u64 / divisor;
+      let (gif, settings) = fit(max_bytes).unwrap();
+      assert!(gif.len() as u64 <= max_bytes, "{divisor}");
+
+      let mut decoder = gif::Decoder::new(&gif[..]).unwrap();
+      assert_eq!(
+        (decoder.screen.width, decoder.screen.height),
+        (settings.width, settings.height)
+      );
+      let mut frames = 0;
+      while decoder.next_image(false).unwrap().is_some() {
+        frames += 1;
+      }
+      assert_eq!(frames, FRAMES.div_ceil(settings.frame_step));
+
+      // The colors are reduced first, then the size down to a half, then the
+      // frame rate and finally the size again
+      if settings.width < 64 {
+        assert_eq!(settings.max_colors, MIN_COLORS);
+      }
+      if settings.frame_step > 1 {
+        assert!(settings.width <= 32);
+      }
+      if settings.width < 32 {
+        assert_eq!(settings.frame_step, MAX_FRAME_STEP);
+      }
+      steps.push((settings.max_colors, settings.width, settings.frame_step));
+    }
+    assert!(steps
+      .windows(2)
+      .all(|v| v[0].0 >= v[1].0 && v[0].1 >= v[1].1 && v[0].2 <= v[1].2));
+    // Down to the colors alone at first, and every stage by the end
+    assert_eq!(steps[0], (MIN_COLORS, 64, 1));
+    assert!(steps.last().is_some_and(|v| v.2 == MAX_FRAME_STEP));
+  }
+
+  #[test]
+  fn fails_once_nothing_is_left_to_shrink() {
+    assert_eq!(
+      fit(1).err().as_deref(),
+      Some("The output does not fit within `CombineOptions`.`maxBytes`")
+    );
+  }
+}
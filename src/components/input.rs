@@ -0,0 +1,57 @@
+use std::{
+  fs::File,
+  io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
+  sync::Arc,
+};
+
+use super::{Source, StreamReader};
+
+/// GIF data read by the native backend, from a file, from memory or from a
+/// stream
+pub(crate) enum Input {
+  File(BufReader<File>),
+  Memory(Cursor<Arc<[u8]>>),
+  Stream(BufReader<StreamReader>),
+}
+
+impl Input {
+  /// With `wait` unset, reading the part of a stream not received yet fails
+  /// with `ErrorKind::WouldBlock`
+  pub(crate) fn open(src: &str, source: &Source, wait: bool) -> io::Result<Self> {
+    match source {
+      Source::File => File::open(src).map(|v| Self::File(BufReader::new(v))),
+      Source::Memory(v) => Ok(Self::Memory(Cursor::new(Arc::clone(v)))),
+      Source::Stream(v) => Ok(Self::Stream(BufReader::new(StreamReader::new(v, wait)))),
+    }
+  }
+}
+
+impl Read for Input {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    match self {
+      Self::File(v) => v.read(buf),
+      Self::Memory(v) => v.read(buf),
+      Self::Stream(v) => v.read(buf),
+    }
+  }
+}
+
+impl Seek for Input {
+  fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+    match self {
+      Self::File(v) => v.seek(pos),
+      Self::Memory(v) => v.seek(pos),
+      Self::Stream(v) => v.seek(pos),
+    }
+  }
+
+  // Not left to the default `seek`, which would discard the `BufReader`
+  // buffer
+  fn stream_position(&mut self) -> io::Result<u64> {
+    match self {
+      Self::File(v) => v.stream_position(),
+      Self::Memory(v) => v.stream_position(),
+      Self::Stream(v) => v.stream_position(),
+    }
+  }
+}
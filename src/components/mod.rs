@@ -1,7 +1,21 @@
+mod backend;
 mod combine;
 mod extract;
+mod fit;
+mod input;
+mod output;
+mod palette;
+mod pixel;
 mod probe;
+mod stream;
 
+pub(crate) use backend::*;
 pub(crate) use combine::*;
 pub(crate) use extract::*;
+pub(crate) use fit::*;
+pub(crate) use input::*;
+pub(crate) use output::*;
+pub(crate) use palette::*;
+pub(crate) use pixel::*;
 pub(crate) use probe::*;
+pub(crate) use stream::*;
@@ -0,0 +1,265 @@
+use std::{
+  borrow::Cow,
+  collections::VecDeque,
+  fs::{self, File},
+  io::{self, BufWriter, ErrorKind, Write},
+  sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::{
+  napi::native::{self, ToNapi},
+  Result,
+};
+
+use super::FitSettings;
+
+/// Number of encoded bytes buffered ahead of the reader before the encoder
+/// starts to wait
+const CHUNKS_CAPACITY: usize = 1 << 20;
+
+#[derive(Default)]
+struct ChunksState {
+  chunks: VecDeque<Vec<u8>>,
+  size: usize,
+  ended: bool,
+  cancelled: bool,
+  reading: bool,
+  error: Option<Cow<'static, str>>,
+  /// The pending read, notified once a chunk or the end is available. Boxed
+  /// like `Stream`'s waiting reads
+  waiting: Option<Box<dyn Send>>,
+}
+
+/// Encoded output handed from the encoder thread over to the reader
+#[derive(Default)]
+pub(crate) struct Chunks {
+  state: Mutex<ChunksState>,
+  changed: Condvar,
+}
+
+impl Chunks {
+  fn lock(&self) -> MutexGuard<'_, ChunksState> {
+    self.state.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  /// Blocks while the buffered output exceeds the capacity
+  fn push(&self, chunk: &[u8]) -> io::Result<()> {
+    let mut state = self.lock();
+    while state.size >= CHUNKS_CAPACITY && !state.cancelled {
+      state = self
+        .changed
+        .wait(state)
+        .unwrap_or_else(PoisonError::into_inner);
+    }
+    if state.cancelled {
+      return Err(io::Error::new(
+        ErrorKind::BrokenPipe,
+        "The output stream was closed",
+      ));
+    }
+    state.size += chunk.len();
+    state.chunks.push_back(chunk.to_vec());
+    state.waiting = None;
+    self.changed.notify_all();
+    Ok(())
+  }
+
+  /// Marks the end of the output. Pending chunks are still readable
+  pub(crate) fn end(&self, error: Option<Cow<'static, str>>) {
+    let mut state = self.lock();
+    state.ended = true;
+    state.error = error;
+    state.waiting = None;
+    self.changed.notify_all();
+  }
+
+  /// Stops accepting output, so the encoder does not wait for a reader that
+  /// is gone
+  pub(crate) fn cancel(&self) {
+    let mut state = self.lock();
+    state.cancelled = true;
+    state.ended = true;
+    state.chunks.clear();
+    state.size = 0;
+    state.waiting = None;
+    self.changed.notify_all();
+  }
+
+  fn pop(&self, state: &mut ChunksState) -> Option<Result<Option<Vec<u8>>>> {
+    if let Some(chunk) = state.chunks.pop_front() {
+      state.size -= chunk.len();
+      self.changed.notify_all();
+      return Some(Ok(Some(chunk)));
+    }
+    if !state.ended {
+      return None;
+    }
+    Some(state.error.clone().map_or(Ok(None), Err))
+  }
+
+  /// Takes the next chunk if one is available. `Some(Ok(None))` marks the
+  /// end of the output. Otherwise the caller has to wait for the chunk with
+  /// `notify`
+  pub(crate) fn try_take(&self) -> Option<Result<Option<Vec<u8>>>> {
+    let mut state = self.lock();
+    if state.reading {
+      return Some(Err(Cow::Borrowed("A chunk read is already in progress")));
+    }
+    let result = self.pop(&mut state);
+    state.reading = result.is_none();
+    result
+  }
+
+  /// Drops `notifier` once the next chunk or the end of the output is
+  /// available to `take`
+  pub(crate) fn notify(&self, notifier: native::Notifier) {
+    let mut state = self.lock();
+    if state.chunks.is_empty() && !state.ended {
+      state.waiting = Some(Box::new(notifier));
+    }
+  }
+
+  /// Takes the chunk announced by `notify`. `None` marks the end of the
+  /// output
+  pub(crate) fn take(&self) -> Result<Option<Vec<u8>>> {
+    let mut state = self.lock();
+    state.reading = false;
+    self.pop(&mut state).unwrap_or(Ok(None))
+  }
+}
+
+/// Destination of the encoded GIF
+pub(crate) enum Sink {
+  Memory(Vec<u8>),
+  Stream {
+    chunks: Arc<Chunks>,
+    bytes: u64,
+  },
+  File {
+    writer: BufWriter<File>,
+    path: String,
+    bytes: u64,
+  },
+}
+
+/// What `Combine.finish()` resolves with
+pub(crate) enum Output {
+  Buffer(Vec<u8>),
+  Streamed {
+    bytes: u64,
+  },
+  File {
+    bytes: u64,
+    path: String,
+  },
+  /// Output fit into `CombineOptions`.`maxBytes`
+  Fitted {
+    output: Box<Output>,
+    settings: FitSettings,
+  },
+}
+
+impl Write for Sink {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.write_all(buf)?;
+    Ok(buf.len())
+  }
+
+  fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+    match self {
+      Self::Memory(v) => v.write_all(buf),
+      Self::Stream { chunks, bytes } => {
+        if !buf.is_empty() {
+          chunks.push(buf)?;
+          *bytes += buf.len() as u64;
+        }
+        Ok(())
+      }
+      Self::File { writer, bytes, .. } => {
+        writer.write_all(buf)?;
+        *bytes += buf.len() as u64;
+        Ok(())
+      }
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self {
+      Self::File { writer, .. } => writer.flush(),
+      Self::Memory(_) | Self::Stream { .. } => Ok(()),
+    }
+  }
+}
+
+impl Sink {
+  /// Creates the output file, replacing an existing one
+  pub(crate) fn create(path: &str) -> Result<Self> {
+    let file = File::create(path)
+      .map_err(|v| Cow::Owned(format!("Failed to create the output file: {v}")))?;
+    Ok(Self::File {
+      writer: BufWriter::new(file),
+      path: path.to_owned(),
+      bytes: 0,
+    })
+  }
+
+  /// Discards the output, removing the partially written file
+  pub(crate) fn abort(self) {
+    if let Self::File { writer, path, .. } = self {
+      // The buffered remainder is not worth writing
+      drop(writer.into_parts());
+      let _ = fs::remove_file(path);
+    }
+  }
+
+  pub(crate) fn finish(mut self) -> io::Result<Output> {
+    self.flush()?;
+    Ok(match self {
+      Self::Memory(v) => Output::Buffer(v),
+      Self::Stream { chunks, bytes } => {
+        chunks.end(None);
+        Output::Streamed { bytes }
+      }
+      Self::File { path, bytes, .. } => Output::File { bytes, path },
+    })
+  }
+}
+
+impl ToNapi for Output {
+  fn to_napi(&mut self, env: native::env) -> Result<native::value> {
+    match self {
+      Self::Buffer(v) => native::create_buffer_copy(env, v),
+      Self::Streamed { bytes } => {
+        let result = native::create_object(env)?;
+        #[allow(clippy::cast_precision_loss)]
+        native::set_named_property(env, result, "bytes", *bytes as f64)?;
+        Ok(result)
+      }
+      Self::File { bytes, path } => {
+        let result = native::create_object(env)?;
+        #[allow(clippy::cast_precision_loss)]
+        native::set_named_property(env, result, "bytes", *bytes as f64)?;
+        native::set_named_property(env, result, "path", path.as_str())?;
+        Ok(result)
+      }
+      Self::Fitted { output, settings } => {
+        let result = match &mut **output {
+          Self::Buffer(v) => {
+            let result = native::create_object(env)?;
+            #[allow(clippy::cast_precision_loss)]
+            native::set_named_property(env, result, "bytes", v.len() as f64)?;
+            native::set_named_property(env, result, "buffer", native::create_buffer_copy(env, v)?)?;
+            result
+          }
+          v => v.to_napi(env)?,
+        };
+        native::set_named_property(env, result, "width", f64::from(settings.width))?;
+        native::set_named_property(env, result, "height", f64::from(settings.height))?;
+        native::set_named_property(env, result, "maxColors", f64::from(settings.max_colors))?;
+        #[allow(clippy::cast_precision_loss)]
+        native::set_named_property(env, result, "frameStep", settings.frame_step as f64)?;
+        Ok(result)
+      }
+    }
+  }
+}
@@ -0,0 +1,182 @@
+use std::borrow::Cow;
+
+use crate::{
+  gif,
+  napi::native::{self, FromNapi},
+  Result,
+};
+
+/// Pixels counted while generating a global palette
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StatsMode {
+  /// Every pixel of every frame
+  #[default]
+  Full,
+  /// Only the pixels that differ from the previous frame
+  Diff,
+}
+
+impl StatsMode {
+  pub(crate) fn as_str(self) -> &'static str {
+    match self {
+      Self::Full => "full",
+      Self::Diff => "diff",
+    }
+  }
+}
+
+impl FromNapi for StatsMode {
+  fn from_napi(env: native::env, v: native::value) -> Result<Self> {
+    match String::from_napi(env, v)?.as_str() {
+      "full" => Ok(Self::Full),
+      "diff" => Ok(Self::Diff),
+      v => Err(Cow::Owned(format!("Unknown stats mode {v:?}"))),
+    }
+  }
+}
+
+/// How color tables are assigned to the frames
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PaletteMode {
+  /// A single global palette generated from every frame
+  #[default]
+  Global,
+  /// A local palette generated from each frame
+  Local,
+  /// The global palette, with a local one for the frames whose mean color
+  /// error on the global palette exceeds the threshold
+  Hybrid(f64),
+}
+
+impl FromNapi for PaletteMode {
+  fn from_napi(env: native::env, v: native::value) -> Result<Self> {
+    match String::from_napi(env, v)?.as_str() {
+      "global" => Ok(Self::Global),
+      "local" => Ok(Self::Local),
+      "hybrid" => Ok(Self::Hybrid(8_f64)),
+      v => Err(Cow::Owned(format!("Unknown palette mode {v:?}"))),
+    }
+  }
+}
+
+/// Fixed global color table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct UserPalette {
+  /// RGB triples
+  pub(crate) colors: Vec<u8>,
+  /// The first entry with zero alpha
+  pub(crate) transparent: Option<u8>,
+}
+
+impl UserPalette {
+  /// Builds a mapper onto the palette. Unless an entry is transparent
+  /// already, an extra entry is reserved for transparent pixels (or for the
+  /// unchanged ones with `reserve`) if there is room for it
+  pub(crate) fn mapper(&self, alpha_threshold: u8, reserve: bool) -> gif::Mapper {
+    let mut colors = self.colors.clone();
+    let transparent = self.transparent.or_else(|| {
+      let len = colors.len() / 3;
+      let index = u8::try_from(len)
+        .ok()
+        .filter(|_| reserve || alpha_threshold > 0)?;
+      colors.extend_from_slice(&[0, 0, 0]);
+      Some(index)
+    });
+    gif::Mapper::new(colors, transparent, alpha_threshold)
+  }
+}
+
+/// Parses either an array of `[r, g, b]` or `[r, g, b, a]` entries, or a
+/// `Buffer` of RGB triples. The first entry with zero alpha becomes the
+/// transparent one
+impl FromNapi for UserPalette {
+  fn from_napi(env: native::env, v: native::value) -> Result<Self> {
+    let invalid = || Cow::Borrowed("Invalid `CombineOptions`.`palette` property");
+    let mut transparent = None;
+    let palette = if native::is_buffer(env, v)? {
+      let buf = native::get_buffer_info(env, v)?;
+      if buf.len() % 3 != 0 {
+        return Err(invalid());
+      }
+      buf.to_vec()
+    } else if native::is_array(env, v)? {
+      let len = native::get_array_length(env, v)?;
+      let mut palette = Vec::with_capacity(len * 3);
+      for i in 0..len {
+        let entry = native::get_property(env, v, i)?;
+        if !native::is_array(env, entry)?
+          || !(3..=4).contains(&native::get_array_length(env, entry)?)
+        {
+          return Err(invalid());
+        }
+        for c in 0..3_usize {
+          let value = native::get_property(env, entry, c)?;
+          if !matches!(native::value_type(env, value)?, native::valuetype::Number) {
+            return Err(invalid());
+          }
+          let value = native::get_value_double(env, value)?;
+          if value.fract() != 0_f64 || !(0_f64..=255_f64).contains(&value) {
+            return Err(invalid());
+          }
+          #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+          palette.push(value as u8);
+        }
+        if native::get_array_length(env, entry)? == 4 && transparent.is_none() {
+          let alpha = native::get_property(env, entry, 3_usize)
+            .and_then(|v| native::get_value_double(env, v))?;
+          if alpha == 0_f64 {
+            transparent = u8::try_from(i).ok();
+          }
+        }
+      }
+      palette
+    } else {
+      return Err(invalid());
+    };
+
+    if !(1..=256).contains(&(palette.len() / 3)) {
+      return Err(Cow::Borrowed(
+        "The palette is expected to hold between 1 and 256 colors",
+      ));
+    }
+    Ok(Self {
+      colors: palette,
+      transparent,
+    })
+  }
+}
+
+/// Creates an array of `[r, g, b]` entries, with the `transparent` one as
+/// `[r, g, b, 0]`, the same shape `CombineOptions`.`palette` accepts
+pub(crate) fn palette_to_napi(
+  env: native::env,
+  colors: &[u8],
+  transparent: Option<u8>,
+) -> Result<native::value> {
+  let result = native::create_array(env, colors.len() / 3)?;
+  for (i, rgb) in colors.chunks_exact(3).enumerate() {
+    let transparent = transparent.is_some_and(|v| usize::from(v) == i);
+    let entry = native::create_array(env, 3 + usize::from(transparent))?;
+    for (j, &v) in rgb.iter().enumerate() {
+      native::set_property(env, entry, j, f64::from(v))?;
+    }
+    if transparent {
+      native::set_property(env, entry, 3_usize, 0_f64)?;
+    }
+    native::set_property(env, result, i, entry)?;
+  }
+  Ok(result)
+}
+
+/// Parses the dither algorithm. The bayer scale is left at its default
+impl FromNapi for gif::Dither {
+  fn from_napi(env: native::env, v: native::value) -> Result<Self> {
+    match String::from_napi(env, v)?.as_str() {
+      "none" => Ok(Self::None),
+      "bayer" => Ok(Self::default()),
+      "floyd_steinberg" => Ok(Self::FloydSteinberg),
+      "sierra" => Ok(Self::Sierra),
+      v => Err(Cow::Owned(format!("Unknown dither {v:?}"))),
+    }
+  }
+}
@@ -0,0 +1,123 @@
+use std::borrow::Cow;
+
+use crate::{
+  gif,
+  napi::native::{self, FromNapi},
+  Result,
+};
+
+use super::UserPalette;
+
+/// Layout of the frame buffers exchanged with JS
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PixelFormat {
+  #[default]
+  Rgba,
+  Bgra,
+  Rgb24,
+  Gray8,
+  /// Palette indices, one byte per pixel
+  Indexed,
+}
+
+impl FromNapi for PixelFormat {
+  fn from_napi(env: native::env, v: native::value) -> Result<Self> {
+    match String::from_napi(env, v)?.as_str() {
+      "rgba" => Ok(Self::Rgba),
+      "bgra" => Ok(Self::Bgra),
+      "rgb24" => Ok(Self::Rgb24),
+      "gray8" => Ok(Self::Gray8),
+      "indexed" => Ok(Self::Indexed),
+      v => Err(Cow::Owned(format!("Unknown pixel format {v:?}"))),
+    }
+  }
+}
+
+/// BT.601 luma
+fn luma(px: &[u8]) -> u8 {
+  let y = u32::from(px[0]) * 299 + u32::from(px[1]) * 587 + u32::from(px[2]) * 114;
+  #[allow(clippy::cast_possible_truncation)]
+  let y = ((y + 500) / 1000) as u8;
+  y
+}
+
+impl PixelFormat {
+  pub(crate) fn bytes_per_pixel(self) -> usize {
+    match self {
+      Self::Rgba | Self::Bgra => 4,
+      Self::Rgb24 => 3,
+      Self::Gray8 | Self::Indexed => 1,
+    }
+  }
+
+  /// The matching `ffmpeg` `pix_fmt`. The indexed format is not exchanged
+  /// with `ffmpeg`
+  pub(crate) fn ffmpeg(self) -> Result<&'static str> {
+    match self {
+      Self::Rgba => Ok("rgba"),
+      Self::Bgra => Ok("bgra"),
+      Self::Rgb24 => Ok("rgb24"),
+      Self::Gray8 => Ok("gray"),
+      Self::Indexed => Err(Cow::Borrowed(
+        "The indexed pixel format is not supported by the ffmpeg backend",
+      )),
+    }
+  }
+
+  /// Converts an RGBA frame into the format, filling `out`. The indexed
+  /// format maps the pixels onto the `mapper` palette
+  pub(crate) fn convert(self, rgba: &[u8], mapper: Option<&mut gif::Mapper>, out: &mut [u8]) {
+    let pixels = rgba.chunks_exact(4);
+    match (self, mapper) {
+      (Self::Rgba, _) => out.copy_from_slice(rgba),
+      (Self::Bgra, _) => {
+        for (px, o) in pixels.zip(out.chunks_exact_mut(4)) {
+          o.copy_from_slice(&[px[2], px[1], px[0], px[3]]);
+        }
+      }
+      (Self::Rgb24, _) => {
+        for (px, o) in pixels.zip(out.chunks_exact_mut(3)) {
+          o.copy_from_slice(&px[..3]);
+        }
+      }
+      (Self::Gray8, _) => {
+        for (px, o) in pixels.zip(out) {
+          *o = luma(px);
+        }
+      }
+      (Self::Indexed, Some(mapper)) => mapper.map_into(rgba, out),
+      (Self::Indexed, None) => out.fill(0),
+    }
+  }
+
+  /// Converts a frame in the format into RGBA. The indexed format looks the
+  /// colors up in the `palette`
+  pub(crate) fn to_rgba(self, buf: &[u8], palette: Option<&UserPalette>, out: &mut Vec<u8>) {
+    out.clear();
+    match (self, palette) {
+      (Self::Rgba, _) => out.extend_from_slice(buf),
+      (Self::Bgra, _) => out.extend(
+        buf
+          .chunks_exact(4)
+          .flat_map(|px| [px[2], px[1], px[0], px[3]]),
+      ),
+      (Self::Rgb24, _) => out.extend(
+        buf
+          .chunks_exact(3)
+          .flat_map(|px| [px[0], px[1], px[2], 255]),
+      ),
+      (Self::Gray8, _) => out.extend(buf.iter().flat_map(|&v| [v, v, v, 255])),
+      (Self::Indexed, Some(palette)) => out.extend(buf.iter().flat_map(|&i| {
+        if palette.transparent == Some(i) {
+          return [0, 0, 0, 0];
+        }
+        let i = usize::from(i) * 3;
+        palette
+          .colors
+          .get(i..i + 3)
+          .map_or([0, 0, 0, 255], |c| [c[0], c[1], c[2], 255])
+      })),
+      (Self::Indexed, None) => out.resize(buf.len() * 4, 0),
+    }
+  }
+}
@@ -2,22 +2,41 @@ use std::{
   alloc::{dealloc, Layout},
   borrow::Cow,
   ffi::c_void,
+  io::ErrorKind,
   mem::swap,
-  process::Command,
   ptr::{addr_of_mut, drop_in_place, null_mut},
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  },
 };
 
 use crate::{
+  gif,
   napi::native::{self, FromNapi, ToNapi},
   Result,
 };
 
-#[derive(Debug)]
+use super::{stream_from_napi, Input, Stream, Until};
+
+/// Where the GIF data is read from
+pub(crate) enum Source {
+  /// The `src` path
+  File,
+  Memory(Arc<[u8]>),
+  /// Data still being received, so the frames are not known ahead
+  Stream(Arc<Stream>),
+}
+
 pub(crate) struct Probe {
+  /// The file path, or a `buffer:<n>` or `stream:<n>` identifier
   pub(crate) src: String,
+  pub(crate) source: Source,
   pub(crate) width: f64,
   pub(crate) height: f64,
-  pub(crate) fps: f64,
+  /// Per-frame delays in centiseconds as stored in the file. Left empty
+  /// for streams
+  pub(crate) delays: Vec<u16>,
 }
 
 impl FromNapi for &mut Probe {
@@ -43,53 +62,70 @@ impl ToNapi for &'static mut Probe {
   }
 }
 
-pub(crate) fn probe_native(src: String) -> Result<Probe> {
-  let metadata = Command::new("ffprobe")
-    .args([
-      "-of",
-      "compact=p=0:s=,:nk=1",
-      "-show_entries",
-      "stream=width,height,r_frame_rate",
-      &src,
-    ])
-    .output()
-    .map_err(|v| Cow::Owned(format!("Failed to probe the asset: {v}")))?;
-
-  if metadata.stdout.is_empty() {
-    return Err(Cow::Borrowed("File not found"));
+impl Probe {
+  /// Opens the GIF data for the native backend. See `Input::open` for `wait`
+  pub(crate) fn open(&self, wait: bool) -> std::io::Result<Input> {
+    Input::open(&self.src, &self.source, wait)
   }
 
-  let mut w = 0_f64;
-  let mut h = -1_f64;
-  let mut fps_dividend = -1_f64;
-  let mut fps_divisor = -1_f64;
-  for b in metadata.stdout {
-    match b {
-      b'\r' | b'\n' => break,
-      b',' | b'/' => {
-        fps_divisor += f64::from(fps_dividend > -1_f64 && fps_divisor < 0_f64);
-        fps_dividend += f64::from(h > -1_f64 && fps_dividend < 0_f64);
-        h += f64::from(h < 0_f64);
-        continue;
-      }
-      _ => {
-        let n = f64::from(b) - 48_f64;
-        fps_divisor = f64::from(fps_divisor > -1_f64) * (fps_divisor * 10_f64 + n + 1_f64) - 1_f64;
-        fps_dividend = f64::from(fps_divisor > -1_f64) * (fps_dividend + 1_f64) - 1_f64
-          + f64::from(fps_divisor < 0_f64 && fps_dividend > -1_f64)
-            * (fps_dividend * 10_f64 + n + 1_f64);
-        h = f64::from(fps_dividend > -1_f64) * (h + 1_f64) - 1_f64
-          + f64::from(fps_dividend < 0_f64 && h > -1_f64) * (h * 10_f64 + n + 1_f64);
-        w = f64::from(h > -1_f64) * w + f64::from(h < 0_f64) * (w * 10_f64 + n);
-      }
+  /// The delays of every frame, not known ahead for streams
+  pub(crate) fn known_delays(&self) -> Option<&[u16]> {
+    match self.source {
+      Source::Stream(_) => None,
+      Source::File | Source::Memory(_) => Some(&self.delays),
+    }
+  }
+
+  /// The delays of the frames received so far
+  fn received_delays(&self) -> Cow<'_, [u16]> {
+    match &self.source {
+      Source::Stream(stream) => Cow::Owned(stream.delays()),
+      Source::File | Source::Memory(_) => Cow::Borrowed(&self.delays),
+    }
+  }
+}
+
+fn fps(delays: &[u16]) -> f64 {
+  // Same fallback `ffmpeg` applies to delays too short to be honored
+  let duration = delays
+    .iter()
+    .map(|&v| u64::from(if v < 2 { 10 } else { v }))
+    .sum::<u64>();
+  if duration == 0 {
+    return 0_f64;
+  }
+  #[allow(clippy::cast_precision_loss)]
+  let fps = delays.len() as f64 * 100_f64 / duration as f64;
+  fps
+}
+
+/// Reads the header, and every image descriptor unless streaming
+pub(crate) fn probe_native(src: String, source: Source) -> Result<Probe> {
+  let input = Input::open(&src, &source, false).map_err(|v| {
+    if let ErrorKind::NotFound = v.kind() {
+      Cow::Borrowed("File not found")
+    } else {
+      Cow::Owned(format!("Failed to probe the asset: {v}"))
+    }
+  })?;
+  let mut decoder = gif::Decoder::new(input)?;
+
+  let mut delays = Vec::new();
+  if !matches!(source, Source::Stream(_)) {
+    while let Some(image) = decoder.next_image(false)? {
+      delays.push(image.control.delay);
+    }
+    if delays.is_empty() {
+      return Err(Cow::Borrowed("The GIF file contains no frames"));
     }
   }
 
   Ok(Probe {
     src,
-    width: w,
-    height: h,
-    fps: fps_dividend / fps_divisor,
+    source,
+    width: f64::from(decoder.screen.width),
+    height: f64::from(decoder.screen.height),
+    delays,
   })
 }
 
@@ -147,7 +183,44 @@ extern "C" fn probe_fps(env: native::env, info: native::value) -> native::value
   let (this, _, _) = native::unwrap_throw!(env, native::get_cb_info(env, info));
   let probe =
     unsafe { &mut *(native::unwrap_throw!(env, native::unwrap(env, this)) as *mut Probe) };
-  native::unwrap_throw!(env, probe.fps.to_napi(env))
+  native::unwrap_throw!(env, fps(&probe.received_delays()).to_napi(env))
+}
+
+extern "C" fn probe_frame_count(env: native::env, info: native::value) -> native::value {
+  let (this, _, _) = native::unwrap_throw!(env, native::get_cb_info(env, info));
+  let probe =
+    unsafe { &mut *(native::unwrap_throw!(env, native::unwrap(env, this)) as *mut Probe) };
+  native::unwrap_throw!(env, probe.received_delays().len().to_napi(env))
+}
+
+extern "C" fn probe_duration(env: native::env, info: native::value) -> native::value {
+  let (this, _, _) = native::unwrap_throw!(env, native::get_cb_info(env, info));
+  let probe =
+    unsafe { &mut *(native::unwrap_throw!(env, native::unwrap(env, this)) as *mut Probe) };
+  let delays = probe.received_delays();
+  let mut duration = delays.iter().map(|&v| f64::from(v)).sum::<f64>() * 10_f64;
+  native::unwrap_throw!(env, duration.to_napi(env))
+}
+
+extern "C" fn probe_delays(env: native::env, info: native::value) -> native::value {
+  let (this, _, _) = native::unwrap_throw!(env, native::get_cb_info(env, info));
+  let probe =
+    unsafe { &mut *(native::unwrap_throw!(env, native::unwrap(env, this)) as *mut Probe) };
+  let received = probe.received_delays();
+  let delays = native::unwrap_throw!(env, native::create_array(env, received.len()));
+  for (i, &v) in received.iter().enumerate() {
+    let delay = native::unwrap_throw!(env, native::create_object(env));
+    native::unwrap_throw!(
+      env,
+      native::set_named_property(env, delay, "cs", f64::from(v))
+    );
+    native::unwrap_throw!(
+      env,
+      native::set_named_property(env, delay, "ms", f64::from(v) * 10_f64)
+    );
+    native::unwrap_throw!(env, native::set_property(env, delays, i, delay));
+  }
+  delays
 }
 
 pub(crate) fn probe_struct(env: native::env) -> Result<native::value> {
@@ -199,6 +272,36 @@ pub(crate) fn probe_struct(env: native::env) -> Result<native::value> {
           attributes: native::property_attribute::Enumerable,
           data: null_mut(),
         },
+        native::property_descriptor {
+          utf8name: null_mut(),
+          name: native::create_string_utf8(env, "frameCount")?,
+          method: None,
+          getter: Some(probe_frame_count),
+          setter: None,
+          value: null_mut(),
+          attributes: native::property_attribute::Enumerable,
+          data: null_mut(),
+        },
+        native::property_descriptor {
+          utf8name: null_mut(),
+          name: native::create_string_utf8(env, "duration")?,
+          method: None,
+          getter: Some(probe_duration),
+          setter: None,
+          value: null_mut(),
+          attributes: native::property_attribute::Enumerable,
+          data: null_mut(),
+        },
+        native::property_descriptor {
+          utf8name: null_mut(),
+          name: native::create_string_utf8(env, "delays")?,
+          method: None,
+          getter: Some(probe_delays),
+          setter: None,
+          value: null_mut(),
+          attributes: native::property_attribute::Enumerable,
+          data: null_mut(),
+        },
       ],
     )?;
     unsafe { PROBE_STRUCT = native::create_reference(env, probe_struct)? };
@@ -208,6 +311,7 @@ pub(crate) fn probe_struct(env: native::env) -> Result<native::value> {
 
 struct ProbeContext {
   s: String,
+  source: Option<Source>,
   async_work: native::async_work,
   deferred: native::deferred,
   result: Result<&'static mut Probe>,
@@ -217,7 +321,8 @@ extern "C" fn probe_execute(_env: native::env, data: *mut c_void) {
   let ctx = unsafe { &mut *data.cast::<ProbeContext>() };
   let mut src = String::new();
   swap(&mut ctx.s, &mut src);
-  ctx.result = probe_native(src).map(Box::new).map(Box::leak);
+  let source = ctx.source.take().unwrap_or(Source::File);
+  ctx.result = probe_native(src, source).map(Box::new).map(Box::leak);
 }
 
 extern "C" fn probe_complete(env: native::env, _status: native::status, data: *mut c_void) {
@@ -238,6 +343,63 @@ extern "C" fn probe_complete(env: native::env, _status: native::status, data: *m
   };
 }
 
+struct ProbeStreamContext {
+  s: String,
+  stream: Arc<Stream>,
+  deferred: native::deferred,
+}
+
+/// Settles once the header of the stream is received, retried as chunks
+/// arrive. Only the header is read, so this runs on the main thread
+fn probe_stream(env: native::env, data: *mut ProbeStreamContext) {
+  let ctx = unsafe { &mut *data };
+  let received = ctx.stream.received();
+  let result = match probe_native(ctx.s.clone(), Source::Stream(Arc::clone(&ctx.stream))) {
+    Err(v) if v == gif::WOULD_BLOCK => match native::Notifier::new(
+      env,
+      "Wait for the GIF header",
+      data.cast(),
+      probe_stream_complete,
+    ) {
+      Ok(notifier) => {
+        ctx.stream.notify(Until::Bytes(received), notifier);
+        return;
+      }
+      Err(v) => Err(v),
+    },
+    v => v,
+  };
+  let _ = match result {
+    Ok(v) => Box::leak(Box::new(v))
+      .to_napi(env)
+      .and_then(|v| native::resolve_deferred(env, ctx.deferred, v)),
+    Err(v) => native::create_string_utf8(env, &v)
+      .and_then(|v| native::reject_deferred(env, ctx.deferred, v)),
+  };
+
+  unsafe { drop_in_place(data) };
+  unsafe {
+    dealloc(data.cast(), Layout::new::<ProbeStreamContext>());
+  };
+}
+
+extern "C" fn probe_stream_complete(
+  env: native::env,
+  _js_callback: native::value,
+  context: *mut c_void,
+  _data: *mut c_void,
+) {
+  let data = context.cast::<ProbeStreamContext>();
+  if env.is_null() {
+    unsafe { drop_in_place(data) };
+    unsafe {
+      dealloc(data.cast(), Layout::new::<ProbeStreamContext>());
+    };
+    return;
+  }
+  probe_stream(env, data);
+}
+
 fn probe_promise(
   env: native::env,
   info: native::callback_info,
@@ -245,16 +407,35 @@ fn probe_promise(
 ) -> Result<()> {
   let (_, args, _) = native::get_cb_info(env, info)?;
   let mut args = args.into_iter();
-  let s = args
+  let v = args
     .next()
-    .ok_or(Cow::Borrowed("Function call expects exactly one argument"))
-    .and_then(|v| {
-      String::from_napi(env, v)
-        .map_err(|_| Cow::Borrowed("The first argument is expected to be of type `string`"))
+    .ok_or(Cow::Borrowed("Function call expects exactly one argument"))?;
+  static SOURCES: AtomicUsize = AtomicUsize::new(0);
+  let id = || SOURCES.fetch_add(1, Ordering::Relaxed);
+  let (s, source) = if native::is_buffer(env, v)? {
+    // In-memory data is copied, so the `Buffer` may be reused right away
+    let data = Arc::from(native::get_buffer_info(env, v)?);
+    (format!("buffer:{id}", id = id()), Source::Memory(data))
+  } else if let Some(stream) = stream_from_napi(env, v)? {
+    let ctx = Box::leak(Box::new(ProbeStreamContext {
+      s: format!("stream:{id}", id = id()),
+      stream,
+      deferred,
+    }));
+    probe_stream(env, ctx);
+    return Ok(());
+  } else {
+    let s = String::from_napi(env, v).map_err(|_| {
+      Cow::Borrowed(
+        "The first argument is expected to be of type `string`, `Buffer` or an async iterable",
+      )
     })?;
+    (s, Source::File)
+  };
 
   let result = Box::leak(Box::new(ProbeContext {
     s,
+    source: Some(source),
     async_work: null_mut(),
     deferred,
     result: Err(Cow::Borrowed("")),
@@ -0,0 +1,479 @@
+use std::{
+  borrow::Cow,
+  io::{self, Cursor, ErrorKind, Read, Seek, SeekFrom},
+  mem::take,
+  sync::{Arc, Condvar, Mutex, MutexGuard},
+};
+
+use crate::{
+  gif,
+  napi::native::{self, FromNapi},
+  Result,
+};
+
+#[derive(Default)]
+struct State {
+  data: Vec<u8>,
+  ended: bool,
+  error: Option<String>,
+  /// Delays of the images received whole so far
+  delays: Vec<u16>,
+  /// Position past the last image received whole, to walk on from
+  scanned: Option<gif::Checkpoint>,
+  /// Reads parked until the data they need is received, each holding a
+  /// `native::Notifier`. Boxed so the test harness, which has no Node.js to
+  /// link against, does not reach the threadsafe functions
+  waiting: Vec<(Until, Box<dyn Send>)>,
+}
+
+/// What a parked read of a `Stream` waits for
+#[derive(Clone, Copy)]
+pub(crate) enum Until {
+  /// More than the given number of bytes received
+  Bytes(usize),
+  /// More than the given number of images received whole
+  Images(usize),
+  /// Every chunk received
+  Ended,
+}
+
+impl State {
+  fn reached(&self, until: Until) -> bool {
+    self.ended
+      || match until {
+        Until::Bytes(v) => self.data.len() > v,
+        Until::Images(v) => self.delays.len() > v,
+        Until::Ended => false,
+      }
+  }
+
+  /// Walks the images received whole since the last call, without decoding
+  /// them
+  fn scan(&mut self) {
+    let Self {
+      data,
+      delays,
+      scanned,
+      ..
+    } = self;
+    let Ok(mut decoder) = gif::Decoder::new(Cursor::new(data.as_slice())) else {
+      return;
+    };
+    if let Some(v) = scanned {
+      if decoder.restore(v).is_err() {
+        return;
+      }
+    }
+    // The last image is left out until received whole
+    while let Ok(Some(image)) = decoder.next_image(false) {
+      delays.push(image.control.delay);
+      match decoder.checkpoint(false) {
+        Ok(v) => *scanned = Some(v),
+        Err(_) => return,
+      }
+    }
+  }
+}
+
+/// GIF data received progressively from a JS async iterable. Everything
+/// received is kept, so the data can be read again once seeking back
+#[derive(Default)]
+pub(crate) struct Stream {
+  state: Mutex<State>,
+  received: Condvar,
+}
+
+impl Stream {
+  fn lock(&self) -> MutexGuard<'_, State> {
+    self
+      .state
+      .lock()
+      .unwrap_or_else(std::sync::PoisonError::into_inner)
+  }
+
+  fn push(&self, chunk: &[u8]) {
+    let mut state = self.lock();
+    state.data.extend_from_slice(chunk);
+    state.scan();
+    let waiting = take(&mut state.waiting);
+    state.waiting = waiting
+      .into_iter()
+      .filter(|(until, _)| !state.reached(*until))
+      .collect();
+    drop(state);
+    self.received.notify_all();
+  }
+
+  fn end(&self, error: Option<String>) {
+    let mut state = self.lock();
+    state.ended = true;
+    state.error = error;
+    state.waiting.clear();
+    drop(state);
+    self.received.notify_all();
+  }
+
+  /// Whether every chunk is received, so reads no longer wait
+  pub(crate) fn is_ended(&self) -> bool {
+    self.lock().ended
+  }
+
+  /// Number of bytes received so far
+  pub(crate) fn received(&self) -> usize {
+    self.lock().data.len()
+  }
+
+  /// Whether a read waiting `until` can go on
+  pub(crate) fn is_reached(&self, until: Until) -> bool {
+    self.lock().reached(until)
+  }
+
+  /// Drops `notifier` once the data a read waits for is received, so the
+  /// read is only attempted then rather than parking a thread meanwhile
+  pub(crate) fn notify(&self, until: Until, notifier: native::Notifier) {
+    let mut state = self.lock();
+    if !state.reached(until) {
+      state.waiting.push((until, Box::new(notifier)));
+    }
+  }
+
+  /// The delays of the images received whole so far
+  pub(crate) fn delays(&self) -> Vec<u16> {
+    self.lock().delays.clone()
+  }
+}
+
+/// Reads a `Stream`. The data not received yet is either waited for, or
+/// fails the read with `ErrorKind::WouldBlock`
+pub(crate) struct StreamReader {
+  stream: Arc<Stream>,
+  position: u64,
+  wait: bool,
+}
+
+impl StreamReader {
+  pub(crate) fn new(stream: &Arc<Stream>, wait: bool) -> Self {
+    Self {
+      stream: Arc::clone(stream),
+      position: 0,
+      wait,
+    }
+  }
+}
+
+impl Read for StreamReader {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let mut state = self.stream.lock();
+    loop {
+      let position = usize::try_from(self.position).unwrap_or(usize::MAX);
+      if let Some(available) = state.data.get(position..).filter(|v| !v.is_empty()) {
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.position += len as u64;
+        return Ok(len);
+      }
+      if let Some(v) = &state.error {
+        return Err(io::Error::other(v.clone()));
+      }
+      if state.ended {
+        return Ok(0);
+      }
+      if !self.wait {
+        return Err(ErrorKind::WouldBlock.into());
+      }
+      state = self
+        .stream
+        .received
+        .wait(state)
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    }
+  }
+}
+
+impl Seek for StreamReader {
+  fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+    let position = match pos {
+      SeekFrom::Start(v) => Some(v),
+      SeekFrom::Current(v) => self.position.checked_add_signed(v),
+      SeekFrom::End(v) => {
+        let mut state = self.stream.lock();
+        while !state.ended {
+          if !self.wait {
+            return Err(ErrorKind::WouldBlock.into());
+          }
+          state = self
+            .stream
+            .received
+            .wait(state)
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        }
+        (state.data.len() as u64).checked_add_signed(v)
+      }
+    };
+    self.position = position.ok_or(io::Error::new(
+      ErrorKind::InvalidInput,
+      "Invalid seek to a negative position",
+    ))?;
+    Ok(self.position)
+  }
+}
+
+/// Pulls the chunks of an async iterator into a `Stream`
+struct Pump {
+  stream: Arc<Stream>,
+  iterator: native::nref,
+}
+
+/// Requests the next chunk, handled by `stream_chunk` or `stream_error`
+fn pump_next(env: native::env, pump: *mut Pump) -> Result<()> {
+  let iterator = native::get_reference_value(env, unsafe { &*pump }.iterator)?;
+  let next = native::get_named_property(env, iterator, "next")?;
+  let promise = native::call_function(env, iterator, next, &[])?;
+  let then = native::get_named_property(env, promise, "then")?;
+  let on_chunk = native::create_function(env, "onChunk", stream_chunk, pump.cast())?;
+  let on_error = native::create_function(env, "onError", stream_error, pump.cast())?;
+  native::call_function(env, promise, then, &[on_chunk, on_error])?;
+  Ok(())
+}
+
+/// Ends the stream and releases the iterator. Unless the iterator is done,
+/// it is returned early, which destroys a `Readable`
+fn pump_finish(env: native::env, pump: *mut Pump, error: Option<String>, done: bool) {
+  let pump = unsafe { Box::from_raw(pump) };
+  pump.stream.end(error);
+  if !done {
+    let _ = native::get_reference_value(env, pump.iterator).and_then(|iterator| {
+      let finish = native::get_named_property(env, iterator, "return")?;
+      if let native::valuetype::Function = native::value_type(env, finish)? {
+        native::call_function(env, iterator, finish, &[])?;
+      }
+      Ok(())
+    });
+  }
+  let _ = native::delete_reference(env, pump.iterator);
+}
+
+extern "C" fn stream_chunk(env: native::env, info: native::callback_info) -> native::value {
+  let (_, args, data) = native::unwrap_throw!(env, native::get_cb_info(env, info));
+  let pump = data.cast::<Pump>();
+  let chunk = args
+    .first()
+    .copied()
+    .ok_or(Cow::Borrowed(""))
+    .and_then(|result| {
+      let done = native::get_named_property(env, result, "done")
+        .and_then(|v| native::get_value_bool(env, v))
+        .unwrap_or(false);
+      if done {
+        return Ok(None);
+      }
+      let value = native::get_named_property(env, result, "value")?;
+      native::get_view_info(env, value)?
+        .ok_or(Cow::Borrowed(
+          "Streamed chunks are expected to be a `Buffer`, a `TypedArray` or a `DataView`",
+        ))
+        .map(Some)
+    });
+  match chunk {
+    Ok(None) => pump_finish(env, pump, None, true),
+    // Nothing reads the stream anymore
+    Ok(Some(_)) if Arc::strong_count(&unsafe { &*pump }.stream) == 1 => {
+      pump_finish(env, pump, None, false);
+    }
+    Ok(Some(chunk)) => {
+      unsafe { &*pump }.stream.push(chunk);
+      if let Err(v) = pump_next(env, pump) {
+        pump_finish(env, pump, Some(v.into_owned()), false);
+      }
+    }
+    Err(v) => pump_finish(env, pump, Some(v.into_owned()), false),
+  }
+  native::undefined(env)
+}
+
+extern "C" fn stream_error(env: native::env, info: native::callback_info) -> native::value {
+  let (_, args, data) = native::unwrap_throw!(env, native::get_cb_info(env, info));
+  let message = args
+    .first()
+    .and_then(|&v| {
+      native::get_named_property(env, v, "message")
+        .and_then(|v| String::from_napi(env, v))
+        .ok()
+    })
+    .unwrap_or_else(|| "The stream failed".to_owned());
+  pump_finish(env, data.cast(), Some(message), true);
+  native::undefined(env)
+}
+
+/// Starts pulling `v` into a `Stream` if it is an async iterable, like a
+/// `Readable`
+pub(crate) fn stream_from_napi(env: native::env, v: native::value) -> Result<Option<Arc<Stream>>> {
+  if !matches!(native::value_type(env, v)?, native::valuetype::Object) {
+    return Ok(None);
+  }
+  let global = native::global(env);
+  let symbol = native::get_named_property(env, global, "Symbol")?;
+  let symbol_async_iterator = native::get_named_property(env, symbol, "asyncIterator")?;
+  let iterate = native::get_property(env, v, symbol_async_iterator)?;
+  if !matches!(
+    native::value_type(env, iterate)?,
+    native::valuetype::Function
+  ) {
+    return Ok(None);
+  }
+
+  let iterator = native::call_function(env, v, iterate, &[])?;
+  let stream = Arc::new(Stream::default());
+  let pump = Box::into_raw(Box::new(Pump {
+    stream: Arc::clone(&stream),
+    iterator: native::create_reference(env, iterator)?,
+  }));
+  if let Err(v) = pump_next(env, pump) {
+    pump_finish(env, pump, None, false);
+    return Err(v);
+  }
+  Ok(Some(stream))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{thread, time::Duration};
+
+  use super::*;
+  use crate::gif::noise;
+
+  const DELAYS: [u16; 5] = [3, 1, 4, 1, 5];
+
+  /// Frames of noise, so none of them compresses to a handful of bytes
+  fn animation() -> Vec<u8> {
+    let palette = noise(16 * 3, 1);
+    let mut encoder = gif::Encoder::new(Vec::new(), 12, 10, Some(&palette), None).unwrap();
+    for (i, &delay) in DELAYS.iter().enumerate() {
+      let indices = noise(12 * 10, u32::from(delay) + u32::try_from(i).unwrap() * 7)
+        .into_iter()
+        .map(|v| v % 16)
+        .collect::<Vec<_>>();
+      encoder
+        .write_frame(&gif::Frame {
+          rect: gif::Rect {
+            left: 0,
+            top: 0,
+            width: 12,
+            height: 10,
+          },
+          delay,
+          disposal: gif::Disposal::Keep,
+          transparent: None,
+          palette: None,
+          indices: &indices,
+        })
+        .unwrap();
+    }
+    encoder.finish().unwrap()
+  }
+
+  /// Number of images decodable from the data received so far
+  fn whole_images(data: &[u8]) -> usize {
+    let Ok(mut decoder) = gif::Decoder::new(data) else {
+      return 0;
+    };
+    let mut count = 0;
+    while let Ok(Some(_)) = decoder.next_image(false) {
+      count += 1;
+    }
+    count
+  }
+
+  #[test]
+  fn tracks_the_images_received_whole() {
+    let data = animation();
+    let stream = Stream::default();
+    let mut received = 0;
+    for chunk in data.chunks(7) {
+      stream.push(chunk);
+      received += chunk.len();
+      let count = whole_images(&data[..received]);
+      assert_eq!(stream.delays(), DELAYS[..count], "{received} bytes");
+    }
+    assert_eq!(stream.delays(), DELAYS);
+  }
+
+  #[test]
+  fn leaves_out_a_cut_off_last_image() {
+    let data = animation();
+    let stream = Stream::default();
+    // Short of the block terminator of the last image and of the trailer
+    stream.push(&data[..data.len() - 2]);
+    assert_eq!(stream.delays(), DELAYS[..DELAYS.len() - 1]);
+    stream.push(&data[data.len() - 2..data.len() - 1]);
+    assert_eq!(stream.delays(), DELAYS);
+
+    // The trailer is not required once the stream ends
+    stream.end(None);
+    let stream = Arc::new(stream);
+    let mut decoder = gif::Decoder::new(StreamReader::new(&stream, false)).unwrap();
+    let mut delays = Vec::new();
+    while let Some(image) = decoder.next_image(false).unwrap() {
+      delays.push(image.control.delay);
+    }
+    assert_eq!(delays, DELAYS);
+  }
+
+  #[test]
+  fn would_block_until_received() {
+    let data = animation();
+    let stream = Arc::new(Stream::default());
+    stream.push(&data[..100]);
+    let mut reader = StreamReader::new(&stream, false);
+    let mut buf = vec![0_u8; 64];
+    assert_eq!(reader.read(&mut buf).unwrap(), 64);
+    assert_eq!(reader.read(&mut buf).unwrap(), 36);
+    assert_eq!(
+      reader.read(&mut buf).unwrap_err().kind(),
+      ErrorKind::WouldBlock
+    );
+    assert_eq!(
+      reader.seek(SeekFrom::End(0)).unwrap_err().kind(),
+      ErrorKind::WouldBlock
+    );
+    assert_eq!(
+      gif::Decoder::new(StreamReader::new(&stream, false))
+        .and_then(|mut v| v.next_image(false).map(|_| ()))
+        .unwrap_err(),
+      gif::WOULD_BLOCK
+    );
+
+    stream.push(&data[100..]);
+    stream.end(None);
+    assert_eq!(reader.seek(SeekFrom::End(0)).unwrap(), data.len() as u64);
+    reader.seek(SeekFrom::Start(100)).unwrap();
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, data[100..]);
+  }
+
+  #[test]
+  fn fails_pending_reads_past_the_received_data() {
+    let data = animation();
+    let stream = Arc::new(Stream::default());
+    stream.push(&data[..100]);
+    let mut reader = StreamReader::new(&stream, true);
+    let pending = thread::spawn(move || {
+      let mut read = Vec::new();
+      let error = reader.read_to_end(&mut read).unwrap_err();
+      (read, error.to_string())
+    });
+    // Parked on the condvar by then, though the outcome is the same if not
+    thread::sleep(Duration::from_millis(50));
+    stream.end(Some("boom".to_owned()));
+    let (read, error) = pending.join().unwrap();
+    // The data received ahead of the error is still read
+    assert_eq!(read, data[..100]);
+    assert_eq!(error, "boom");
+
+    let mut reader = StreamReader::new(&stream, false);
+    let mut buf = vec![0_u8; 100];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(reader.read(&mut buf).unwrap_err().to_string(), "boom");
+  }
+}
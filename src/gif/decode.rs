@@ -0,0 +1,704 @@
+use std::{
+  borrow::Cow,
+  io::{self, ErrorKind, Read, Seek, SeekFrom},
+};
+
+use crate::Result;
+
+use super::{lzw, Disposal, Rect};
+
+pub(crate) struct Screen {
+  pub(crate) width: u16,
+  pub(crate) height: u16,
+  pub(crate) palette: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub(crate) struct Control {
+  pub(crate) disposal: Disposal,
+  /// Delay in centiseconds
+  pub(crate) delay: u16,
+  pub(crate) transparent: Option<u8>,
+}
+
+/// A single image descriptor as stored in the file
+#[derive(Default)]
+pub(crate) struct Image {
+  pub(crate) rect: Rect,
+  pub(crate) interlaced: bool,
+  pub(crate) palette: Option<Vec<u8>>,
+  pub(crate) control: Control,
+  pub(crate) min_code_size: u8,
+  /// Color table indices in row-major order. Left empty when the image
+  /// was read without decoding
+  pub(crate) indices: Vec<u8>,
+}
+
+pub(crate) struct Decoder<R> {
+  reader: R,
+  pub(crate) screen: Screen,
+  pub(crate) repeat: Option<u16>,
+  control: Option<Control>,
+  image: Image,
+  lzw: lzw::Decoder,
+  data: Vec<u8>,
+  scratch: Vec<u8>,
+  canvas: Vec<u8>,
+  backup: Vec<u8>,
+  dispose: Option<(Disposal, Rect)>,
+  /// Whether an image was read whole, so the data may end without a
+  /// trailer
+  complete: bool,
+  done: bool,
+}
+
+/// Canvas state ahead of a frame, as left by the previous ones
+struct Composited {
+  canvas: Vec<u8>,
+  backup: Vec<u8>,
+  dispose: Option<(Disposal, Rect)>,
+}
+
+/// Position ahead of a frame to resume decoding from. Only the frames not
+/// depending on the previous ones can be resumed from without the
+/// composited state
+pub(crate) struct Checkpoint {
+  offset: u64,
+  composited: Option<Box<Composited>>,
+}
+
+impl Checkpoint {
+  /// The memory held by the composited state
+  pub(crate) fn size(&self) -> usize {
+    self
+      .composited
+      .as_ref()
+      .map_or(0, |v| v.canvas.len() + v.backup.len())
+  }
+
+  /// Drops the composited state, once the frame turns out not to need it
+  pub(crate) fn forget(&mut self) {
+    self.composited = None;
+  }
+}
+
+/// The error of a read reaching the part of a stream not received yet
+pub(crate) const WOULD_BLOCK: &str = "The GIF data is not received yet";
+
+fn io_error(v: io::Error) -> Cow<'static, str> {
+  match v.kind() {
+    ErrorKind::UnexpectedEof => Cow::Borrowed("Unexpected end of GIF data"),
+    ErrorKind::WouldBlock => Cow::Borrowed(WOULD_BLOCK),
+    _ => Cow::Owned(v.to_string()),
+  }
+}
+
+impl<R: Read> Decoder<R> {
+  pub(crate) fn new(mut reader: R) -> Result<Self> {
+    let mut header = [0_u8; 13];
+    reader.read_exact(&mut header[..6]).map_err(|v| {
+      if let ErrorKind::UnexpectedEof = v.kind() {
+        Cow::Borrowed("Not a GIF file")
+      } else {
+        io_error(v)
+      }
+    })?;
+    if &header[..6] != b"GIF87a" && &header[..6] != b"GIF89a" {
+      return Err(Cow::Borrowed("Not a GIF file"));
+    }
+    reader.read_exact(&mut header[6..]).map_err(io_error)?;
+
+    let width = u16::from_le_bytes([header[6], header[7]]);
+    let height = u16::from_le_bytes([header[8], header[9]]);
+    let flags = header[10];
+    let palette = if flags & 0x80 == 0 {
+      None
+    } else {
+      let mut palette = vec![0_u8; 3 << ((flags & 0x07) + 1)];
+      reader.read_exact(&mut palette).map_err(io_error)?;
+      Some(palette)
+    };
+
+    Ok(Self {
+      reader,
+      screen: Screen {
+        width,
+        height,
+        palette,
+      },
+      repeat: None,
+      control: None,
+      image: Image::default(),
+      lzw: lzw::Decoder::default(),
+      data: Vec::new(),
+      scratch: Vec::new(),
+      canvas: Vec::new(),
+      backup: Vec::new(),
+      dispose: None,
+      complete: false,
+      done: false,
+    })
+  }
+
+  fn read_u8(&mut self) -> Result<u8> {
+    let mut b = [0_u8; 1];
+    self.reader.read_exact(&mut b).map_err(io_error)?;
+    Ok(b[0])
+  }
+
+  /// Reads data sub-blocks up to the block terminator, appending their
+  /// contents to `into` when provided
+  fn read_sub_blocks(&mut self, mut into: Option<&mut Vec<u8>>) -> Result<()> {
+    let mut block = [0_u8; 255];
+    loop {
+      let len = usize::from(self.read_u8()?);
+      if len == 0 {
+        return Ok(());
+      }
+      self
+        .reader
+        .read_exact(&mut block[..len])
+        .map_err(io_error)?;
+      if let Some(v) = &mut into {
+        v.extend_from_slice(&block[..len]);
+      }
+    }
+  }
+
+  fn read_extension(&mut self) -> Result<()> {
+    match self.read_u8()? {
+      0xF9 => {
+        let mut block = [0_u8; 5];
+        self.reader.read_exact(&mut block).map_err(io_error)?;
+        if block[0] != 4 {
+          return Err(Cow::Borrowed("Invalid Graphic Control Extension"));
+        }
+        self.control = Some(Control {
+          disposal: Disposal::from_bits((block[1] >> 2) & 0x07),
+          delay: u16::from_le_bytes([block[2], block[3]]),
+          transparent: (block[1] & 0x01 != 0).then_some(block[4]),
+        });
+        self.read_sub_blocks(None)
+      }
+      0xFF => {
+        let mut data = Vec::new();
+        self.read_sub_blocks(Some(&mut data))?;
+        let looping = data.starts_with(b"NETSCAPE2.0") || data.starts_with(b"ANIMEXTS1.0");
+        if looping && data.len() >= 14 && data[11] == 1 {
+          self.repeat = Some(u16::from_le_bytes([data[12], data[13]]));
+        }
+        Ok(())
+      }
+      _ => self.read_sub_blocks(None),
+    }
+  }
+
+  fn read_image(&mut self, decode: bool) -> Result<bool> {
+    loop {
+      if self.done {
+        return Ok(false);
+      }
+      let mut b = [0_u8; 1];
+      match self.reader.read_exact(&mut b) {
+        Ok(()) => {}
+        // The trailer is often left out, which browsers and `ffmpeg` accept
+        Err(v) if v.kind() == ErrorKind::UnexpectedEof && self.complete => {
+          self.done = true;
+          continue;
+        }
+        Err(v) => return Err(io_error(v)),
+      }
+      match b[0] {
+        0x21 => self.read_extension()?,
+        0x2C => break,
+        0x3B => self.done = true,
+        v => return Err(Cow::Owned(format!("Invalid GIF block 0x{v:02X}"))),
+      }
+    }
+
+    let mut descriptor = [0_u8; 9];
+    self.reader.read_exact(&mut descriptor).map_err(io_error)?;
+    let flags = descriptor[8];
+    let palette = if flags & 0x80 == 0 {
+      None
+    } else {
+      let mut palette = vec![0_u8; 3 << ((flags & 0x07) + 1)];
+      self.reader.read_exact(&mut palette).map_err(io_error)?;
+      Some(palette)
+    };
+    let min_code_size = self.read_u8()?;
+
+    let image = &mut self.image;
+    image.rect = Rect {
+      left: u16::from_le_bytes([descriptor[0], descriptor[1]]),
+      top: u16::from_le_bytes([descriptor[2], descriptor[3]]),
+      width: u16::from_le_bytes([descriptor[4], descriptor[5]]),
+      height: u16::from_le_bytes([descriptor[6], descriptor[7]]),
+    };
+    image.interlaced = flags & 0x40 != 0;
+    image.control = self.control.take().unwrap_or_default();
+    image.palette = palette;
+    image.min_code_size = min_code_size;
+    image.indices.clear();
+
+    if !decode {
+      self.read_sub_blocks(None)?;
+      self.complete = true;
+      return Ok(true);
+    }
+
+    let mut data = std::mem::take(&mut self.data);
+    data.clear();
+    let read = self.read_sub_blocks(Some(&mut data));
+    self.data = data;
+    read?;
+
+    let image = &mut self.image;
+    let len = usize::from(image.rect.width) * usize::from(image.rect.height);
+    if image.interlaced {
+      self.scratch.clear();
+      self
+        .lzw
+        .decode(image.min_code_size, &self.data, len, &mut self.scratch)?;
+      self.scratch.resize(len, 0);
+      image.indices.resize(len, 0);
+      let width = usize::from(image.rect.width);
+      let height = usize::from(image.rect.height);
+      let rows = [(0, 8), (4, 8), (2, 4), (1, 2)]
+        .into_iter()
+        .flat_map(|(start, step)| (start..height).step_by(step));
+      for (src, dst) in rows.enumerate() {
+        image.indices[dst * width..(dst + 1) * width]
+          .copy_from_slice(&self.scratch[src * width..(src + 1) * width]);
+      }
+    } else {
+      self
+        .lzw
+        .decode(image.min_code_size, &self.data, len, &mut image.indices)?;
+      image.indices.resize(len, 0);
+    }
+    self.complete = true;
+    Ok(true)
+  }
+
+  /// Reads the next image descriptor without compositing it. With `decode`
+  /// unset, the image data is skipped
+  pub(crate) fn next_image(&mut self, decode: bool) -> Result<Option<&Image>> {
+    Ok(self.read_image(decode)?.then_some(&self.image))
+  }
+
+  /// The last image read
+  pub(crate) fn image(&self) -> &Image {
+    &self.image
+  }
+
+  /// Whether the last image covers the whole canvas opaquely and is not
+  /// disposed to the previous state, so the frames from it onward do not
+  /// depend on the earlier ones
+  pub(crate) fn is_keyframe(&self) -> bool {
+    let image = &self.image;
+    image.rect.left == 0
+      && image.rect.top == 0
+      && image.rect.width >= self.screen.width
+      && image.rect.height >= self.screen.height
+      && image.control.disposal != Disposal::Previous
+      && !image
+        .control
+        .transparent
+        .is_some_and(|v| image.indices.contains(&v))
+  }
+
+  /// Reads the next image and composites it onto the full RGBA canvas
+  pub(crate) fn next_frame(&mut self) -> Result<Option<&[u8]>> {
+    if self.canvas.is_empty() {
+      let len = usize::from(self.screen.width) * usize::from(self.screen.height) * 4;
+      self.canvas.resize(len, 0);
+    }
+    if let Some((disposal, rect)) = self.dispose.take() {
+      match disposal {
+        Disposal::Background => self.fill(rect),
+        Disposal::Previous => self.canvas.copy_from_slice(&self.backup),
+        Disposal::Unspecified | Disposal::Keep => {}
+      }
+    }
+
+    if !self.read_image(true)? {
+      return Ok(None);
+    }
+
+    let image = &self.image;
+    if let Disposal::Previous = image.control.disposal {
+      self.backup.clone_from(&self.canvas);
+    }
+    let palette = image
+      .palette
+      .as_deref()
+      .or(self.screen.palette.as_deref())
+      .ok_or(Cow::Borrowed("The image has no color table"))?;
+
+    let canvas_width = usize::from(self.screen.width);
+    let left = usize::from(image.rect.left);
+    let top = usize::from(image.rect.top);
+    let width = usize::from(image.rect.width);
+    let right = (left + width).min(canvas_width);
+    let bottom = (top + usize::from(image.rect.height)).min(usize::from(self.screen.height));
+    for y in (left < right).then_some(top..bottom).into_iter().flatten() {
+      let row = &image.indices[(y - top) * width..][..right - left];
+      let offset = (y * canvas_width + left) * 4;
+      for (&i, px) in row.iter().zip(self.canvas[offset..].chunks_exact_mut(4)) {
+        if image.control.transparent == Some(i) {
+          continue;
+        }
+        let i = usize::from(i) * 3;
+        if let Some(rgb) = palette.get(i..i + 3) {
+          px[..3].copy_from_slice(rgb);
+        } else {
+          px[..3].fill(0);
+        }
+        px[3] = 255;
+      }
+    }
+
+    self.dispose = Some((image.control.disposal, image.rect));
+    Ok(Some(&self.canvas))
+  }
+
+  fn fill(&mut self, rect: Rect) {
+    let canvas_width = usize::from(self.screen.width);
+    let left = usize::from(rect.left).min(canvas_width);
+    let right = (usize::from(rect.left) + usize::from(rect.width)).min(canvas_width);
+    let top = usize::from(rect.top);
+    let bottom = (top + usize::from(rect.height)).min(usize::from(self.screen.height));
+    for y in top..bottom {
+      self.canvas[(y * canvas_width + left) * 4..(y * canvas_width + right) * 4].fill(0);
+    }
+  }
+}
+
+impl<R: Read + Seek> Decoder<R> {
+  /// Marks the position ahead of the next frame. With `composited` set,
+  /// the canvas state is kept too, so any frame can be resumed from
+  pub(crate) fn checkpoint(&mut self, composited: bool) -> Result<Checkpoint> {
+    Ok(Checkpoint {
+      offset: self.reader.stream_position().map_err(io_error)?,
+      composited: composited.then(|| {
+        Box::new(Composited {
+          canvas: self.canvas.clone(),
+          backup: self.backup.clone(),
+          dispose: self.dispose,
+        })
+      }),
+    })
+  }
+
+  /// Resumes decoding at the checkpoint
+  pub(crate) fn restore(&mut self, checkpoint: &Checkpoint) -> Result<()> {
+    self
+      .reader
+      .seek(SeekFrom::Start(checkpoint.offset))
+      .map_err(io_error)?;
+    self.control = None;
+    self.done = false;
+    match &checkpoint.composited {
+      Some(v) => {
+        self.canvas.clone_from(&v.canvas);
+        self.backup.clone_from(&v.backup);
+        self.dispose = v.dispose;
+      }
+      None => self.dispose = None,
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Black, red, green, blue
+  const GLOBAL: [u8; 12] = [0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255];
+  /// Yellow, cyan, magenta, white
+  const LOCAL: [u8; 12] = [255, 255, 0, 0, 255, 255, 255, 0, 255, 255, 255, 255];
+
+  const NONE: [u8; 4] = [0, 0, 0, 0];
+  const BLACK: [u8; 4] = [0, 0, 0, 255];
+  const RED: [u8; 4] = [255, 0, 0, 255];
+  const GREEN: [u8; 4] = [0, 255, 0, 255];
+  const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+  #[derive(Default)]
+  struct Block<'a> {
+    rect: Rect,
+    disposal: Disposal,
+    transparent: Option<u8>,
+    palette: Option<&'a [u8]>,
+    interlaced: bool,
+    /// In the order stored in the file
+    indices: &'a [u8],
+  }
+
+  fn rect(left: u16, top: u16, width: u16, height: u16) -> Rect {
+    Rect {
+      left,
+      top,
+      width,
+      height,
+    }
+  }
+
+  /// Codes every index right after a clear code, so the 3-bit code width
+  /// never grows, independently of the crate's encoder
+  fn lzw_data(indices: &[u8]) -> Vec<u8> {
+    let mut codes = Vec::new();
+    let (mut acc, mut bits) = (0_u32, 0);
+    for code in indices.iter().flat_map(|&i| [4, u32::from(i)]).chain([5]) {
+      acc |= code << bits;
+      bits += 3;
+      while bits >= 8 {
+        codes.push(acc.to_le_bytes()[0]);
+        acc >>= 8;
+        bits -= 8;
+      }
+    }
+    if bits > 0 {
+      codes.push(acc.to_le_bytes()[0]);
+    }
+
+    let mut data = vec![2];
+    for block in codes.chunks(255) {
+      data.push(u8::try_from(block.len()).unwrap());
+      data.extend_from_slice(block);
+    }
+    data.push(0);
+    data
+  }
+
+  fn gif(width: u16, height: u16, blocks: &[Block]) -> Vec<u8> {
+    let mut data = b"GIF89a".to_vec();
+    data.extend_from_slice(&width.to_le_bytes());
+    data.extend_from_slice(&height.to_le_bytes());
+    data.extend_from_slice(&[0x81, 0, 0]);
+    data.extend_from_slice(&GLOBAL);
+    for block in blocks {
+      data.extend_from_slice(&[
+        0x21,
+        0xF9,
+        0x04,
+        (block.disposal as u8) << 2 | u8::from(block.transparent.is_some()),
+        0,
+        0,
+        block.transparent.unwrap_or(0),
+        0,
+        0x2C,
+      ]);
+      for v in [
+        block.rect.left,
+        block.rect.top,
+        block.rect.width,
+        block.rect.height,
+      ] {
+        data.extend_from_slice(&v.to_le_bytes());
+      }
+      data.push(block.palette.map_or(0, |_| 0x81) | if block.interlaced { 0x40 } else { 0 });
+      data.extend_from_slice(block.palette.unwrap_or_default());
+      data.extend(lzw_data(block.indices));
+    }
+    data.push(0x3B);
+    data
+  }
+
+  /// Every composited frame, as RGBA pixels
+  fn frames(data: &[u8]) -> Vec<Vec<[u8; 4]>> {
+    let mut decoder = Decoder::new(data).unwrap();
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.next_frame().unwrap() {
+      frames.push(
+        frame
+          .chunks_exact(4)
+          .map(|v| v.try_into().unwrap())
+          .collect(),
+      );
+    }
+    frames
+  }
+
+  #[test]
+  fn accepts_missing_trailers() {
+    let blocks = [
+      Block {
+        rect: rect(0, 0, 2, 1),
+        indices: &[1, 2],
+        ..Block::default()
+      },
+      Block {
+        rect: rect(1, 0, 1, 1),
+        indices: &[3],
+        ..Block::default()
+      },
+    ];
+    let mut data = gif(2, 1, &blocks);
+    assert_eq!(data.pop(), Some(0x3B));
+    assert_eq!(frames(&data), [[RED, GREEN], [RED, BLUE]]);
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    assert!(decoder.next_image(false).unwrap().is_some());
+    assert!(decoder.next_image(false).unwrap().is_some());
+    assert!(decoder.next_image(false).unwrap().is_none());
+
+    // Within an image or an extension, or ahead of any image
+    for len in [data.len() - 1, data.len() - 9, 13 + GLOBAL.len() + 4] {
+      let mut decoder = Decoder::new(&data[..len]).unwrap();
+      let error = loop {
+        match decoder.next_frame() {
+          Ok(Some(_)) => {}
+          Ok(None) => panic!("{len} bytes decoded without errors"),
+          Err(v) => break v,
+        }
+      };
+      assert_eq!(error, "Unexpected end of GIF data");
+    }
+    let mut decoder = Decoder::new(&data[..13 + GLOBAL.len()]).unwrap();
+    assert!(decoder.next_image(false).is_err());
+  }
+
+  #[test]
+  fn deinterlaces_rows() {
+    // Rows 0 and 8, then 4, then 2 and 6, then the odd ones
+    let data = gif(
+      1,
+      10,
+      &[Block {
+        rect: rect(0, 0, 1, 10),
+        interlaced: true,
+        indices: &[0, 0, 0, 2, 2, 1, 3, 1, 3, 1],
+        ..Block::default()
+      }],
+    );
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    let image = decoder.next_image(true).unwrap().unwrap();
+    assert!(image.interlaced);
+    assert_eq!(image.indices, [0, 1, 2, 3, 0, 1, 2, 3, 0, 1]);
+    assert!(decoder.next_image(true).unwrap().is_none());
+  }
+
+  #[test]
+  fn disposes_to_background() {
+    let frames = frames(&gif(
+      2,
+      2,
+      &[
+        Block {
+          rect: rect(0, 0, 2, 2),
+          indices: &[1, 1, 1, 1],
+          ..Block::default()
+        },
+        Block {
+          rect: rect(1, 1, 1, 1),
+          disposal: Disposal::Background,
+          indices: &[2],
+          ..Block::default()
+        },
+        Block {
+          rect: rect(0, 0, 1, 1),
+          indices: &[3],
+          ..Block::default()
+        },
+      ],
+    ));
+    assert_eq!(frames[1], [RED, RED, RED, GREEN]);
+    assert_eq!(frames[2], [BLUE, RED, RED, NONE]);
+  }
+
+  #[test]
+  fn disposes_to_previous() {
+    let frames = frames(&gif(
+      2,
+      2,
+      &[
+        Block {
+          rect: rect(0, 0, 2, 2),
+          disposal: Disposal::Keep,
+          indices: &[1, 1, 1, 1],
+          ..Block::default()
+        },
+        Block {
+          rect: rect(0, 0, 1, 1),
+          disposal: Disposal::Previous,
+          indices: &[2],
+          ..Block::default()
+        },
+        Block {
+          rect: rect(1, 1, 1, 1),
+          indices: &[3],
+          ..Block::default()
+        },
+      ],
+    ));
+    assert_eq!(frames[1], [GREEN, RED, RED, RED]);
+    assert_eq!(frames[2], [RED, RED, RED, BLUE]);
+  }
+
+  #[test]
+  fn skips_transparent_pixels() {
+    let data = gif(
+      2,
+      2,
+      &[
+        Block {
+          rect: rect(0, 0, 2, 2),
+          transparent: Some(0),
+          indices: &[0, 1, 0, 2],
+          ..Block::default()
+        },
+        Block {
+          rect: rect(0, 0, 2, 2),
+          transparent: Some(3),
+          indices: &[3, 3, 0, 3],
+          ..Block::default()
+        },
+      ],
+    );
+    let frames = frames(&data);
+    assert_eq!(frames[0], [NONE, RED, NONE, GREEN]);
+    assert_eq!(frames[1], [NONE, RED, BLACK, GREEN]);
+
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    decoder.next_frame().unwrap();
+    assert!(!decoder.is_keyframe());
+    assert_eq!(decoder.image().control.transparent, Some(0));
+  }
+
+  #[test]
+  fn prefers_local_palettes() {
+    let data = gif(
+      2,
+      1,
+      &[
+        Block {
+          rect: rect(0, 0, 2, 1),
+          palette: Some(&LOCAL),
+          indices: &[0, 3],
+          ..Block::default()
+        },
+        Block {
+          rect: rect(1, 0, 1, 1),
+          indices: &[3],
+          ..Block::default()
+        },
+      ],
+    );
+    let frames = frames(&data);
+    assert_eq!(frames[0], [[255, 255, 0, 255], [255, 255, 255, 255]]);
+    assert_eq!(frames[1], [[255, 255, 0, 255], BLUE]);
+
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    let image = decoder.next_image(false).unwrap().unwrap();
+    assert_eq!(image.palette.as_deref(), Some(&LOCAL[..]));
+    assert!(image.indices.is_empty());
+    assert!(decoder
+      .next_image(false)
+      .unwrap()
+      .unwrap()
+      .palette
+      .is_none());
+  }
+}
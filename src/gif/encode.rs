@@ -0,0 +1,282 @@
+use std::{borrow::Cow, io::Write};
+
+use crate::Result;
+
+use super::{lzw, Disposal, Rect};
+
+pub(crate) struct Frame<'a> {
+  pub(crate) rect: Rect,
+  /// Delay in centiseconds
+  pub(crate) delay: u16,
+  pub(crate) disposal: Disposal,
+  pub(crate) transparent: Option<u8>,
+  /// Local color table. The global one is used when omitted
+  pub(crate) palette: Option<&'a [u8]>,
+  pub(crate) indices: &'a [u8],
+}
+
+pub(crate) struct Encoder<W> {
+  writer: W,
+  global_bits: u8,
+  /// The global color table, looked up by the lossy compression
+  global: Vec<u8>,
+  /// The maximum RGB distance of the colors the lossy compression replaces
+  lossy: u8,
+  lzw: lzw::Encoder,
+  data: Vec<u8>,
+}
+
+/// Returns the color table size exponent for a table of `palette` RGB triples
+fn table_bits(palette: &[u8]) -> u8 {
+  let colors = (palette.len() / 3).clamp(2, 256);
+  #[allow(clippy::cast_possible_truncation)]
+  let bits = (usize::BITS - (colors - 1).leading_zeros()) as u8;
+  bits
+}
+
+fn write_table(writer: &mut impl Write, palette: &[u8], bits: u8) -> std::io::Result<()> {
+  let len = 3 << bits;
+  writer.write_all(&palette[..palette.len().min(len)])?;
+  writer.write_all(&vec![0_u8; len.saturating_sub(palette.len())])
+}
+
+impl<W: Write> Encoder<W> {
+  /// Writes the header, the logical screen descriptor with the optional
+  /// global color table and, if `repeat` is set, the NETSCAPE2.0 loop
+  /// extension
+  pub(crate) fn new(
+    mut writer: W,
+    width: u16,
+    height: u16,
+    palette: Option<&[u8]>,
+    repeat: Option<u16>,
+  ) -> Result<Self> {
+    let global_bits = palette.map_or(0, table_bits);
+    let mut header = Vec::with_capacity(13 + (3 << global_bits) + 19);
+    header.extend_from_slice(b"GIF89a");
+    header.extend_from_slice(&width.to_le_bytes());
+    header.extend_from_slice(&height.to_le_bytes());
+    header.extend_from_slice(&[palette.map_or(0, |_| 0xF0 | (global_bits - 1)), 0, 0]);
+    if let Some(palette) = palette {
+      write_table(&mut header, palette, global_bits).map_err(|v| Cow::Owned(v.to_string()))?;
+    }
+    if let Some(repeat) = repeat {
+      header.extend_from_slice(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01");
+      header.extend_from_slice(&repeat.to_le_bytes());
+      header.push(0);
+    }
+    writer
+      .write_all(&header)
+      .map_err(|v| Cow::Owned(v.to_string()))?;
+
+    Ok(Self {
+      writer,
+      global_bits,
+      global: palette.map(<[u8]>::to_vec).unwrap_or_default(),
+      lossy: 0,
+      lzw: lzw::Encoder::default(),
+      data: Vec::new(),
+    })
+  }
+
+  /// Compresses the frames lossily, replacing indices with the ones whose
+  /// colors are within the `lossy` RGB distance where that extends a string
+  pub(crate) fn with_lossy(mut self, lossy: u8) -> Self {
+    self.lossy = lossy;
+    self
+  }
+
+  pub(crate) fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+    let local_bits = frame.palette.map(table_bits);
+    let Some(bits) = local_bits.or((self.global_bits > 0).then_some(self.global_bits)) else {
+      return Err(Cow::Borrowed("The frame has no color table"));
+    };
+
+    let data = &mut self.data;
+    data.clear();
+    data.extend_from_slice(&[
+      0x21,
+      0xF9,
+      0x04,
+      (frame.disposal as u8) << 2 | u8::from(frame.transparent.is_some()),
+    ]);
+    data.extend_from_slice(&frame.delay.to_le_bytes());
+    data.extend_from_slice(&[frame.transparent.unwrap_or(0), 0, 0x2C]);
+    for v in [
+      frame.rect.left,
+      frame.rect.top,
+      frame.rect.width,
+      frame.rect.height,
+    ] {
+      data.extend_from_slice(&v.to_le_bytes());
+    }
+    data.push(local_bits.map_or(0, |v| 0x80 | (v - 1)));
+    if let (Some(palette), Some(bits)) = (frame.palette, local_bits) {
+      write_table(data, palette, bits).map_err(|v| Cow::Owned(v.to_string()))?;
+    }
+
+    let min_code_size = bits.max(2);
+    data.push(min_code_size);
+    let start = data.len();
+    let lossy = (self.lossy > 0).then(|| lzw::Lossy {
+      colors: frame.palette.unwrap_or(&self.global),
+      transparent: frame.transparent,
+      distance: u32::from(self.lossy).pow(2),
+    });
+    self
+      .lzw
+      .encode(min_code_size, frame.indices, lossy.as_ref(), data);
+
+    let compressed = data.split_off(start);
+    for block in compressed.chunks(255) {
+      #[allow(clippy::cast_possible_truncation)]
+      data.push(block.len() as u8);
+      data.extend_from_slice(block);
+    }
+    data.push(0);
+
+    self
+      .writer
+      .write_all(data)
+      .map_err(|v| Cow::Owned(v.to_string()))
+  }
+
+  /// Writes the trailer and returns the underlying writer
+  pub(crate) fn finish(mut self) -> Result<W> {
+    self
+      .writer
+      .write_all(&[0x3B])
+      .and_then(|()| self.writer.flush())
+      .map_err(|v| Cow::Owned(v.to_string()))?;
+    Ok(self.writer)
+  }
+
+  /// Returns the underlying writer, leaving the GIF unfinished
+  pub(crate) fn into_inner(self) -> W {
+    self.writer
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::gif::{Decoder, Histogram};
+
+  const WIDTH: u16 = 128;
+  const HEIGHT: u16 = 96;
+
+  /// Writes the frames into a GIF of the `palette` global color table
+  fn encode(palette: &[u8], frames: &[Frame]) -> Vec<u8> {
+    let mut encoder = Encoder::new(Vec::new(), WIDTH, HEIGHT, Some(palette), Some(0)).unwrap();
+    for frame in frames {
+      encoder.write_frame(frame).unwrap();
+    }
+    encoder.finish().unwrap()
+  }
+
+  fn full(indices: &[u8]) -> Frame<'_> {
+    Frame {
+      rect: Rect {
+        left: 0,
+        top: 0,
+        width: WIDTH,
+        height: HEIGHT,
+      },
+      delay: 4,
+      disposal: Disposal::Keep,
+      transparent: None,
+      palette: None,
+      indices,
+    }
+  }
+
+  #[test]
+  fn round_trips_256_colors() {
+    let palette = super::super::noise(256 * 3, 1);
+    // Noise of 256 colors takes several times the 4096 codes, so the
+    // table is reset and every code width is used
+    let first = super::super::noise(usize::from(WIDTH) * usize::from(HEIGHT), 2);
+    let local = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+    let second = super::super::noise(40 * 30, 3)
+      .into_iter()
+      .map(|v| v & 3)
+      .collect::<Vec<_>>();
+    let data = encode(
+      &palette,
+      &[
+        full(&first),
+        Frame {
+          rect: Rect {
+            left: 10,
+            top: 20,
+            width: 40,
+            height: 30,
+          },
+          delay: 7,
+          disposal: Disposal::Background,
+          transparent: Some(3),
+          palette: Some(&local),
+          indices: &second,
+        },
+      ],
+    );
+
+    let mut decoder = Decoder::new(&data[..]).unwrap();
+    assert_eq!(
+      (decoder.screen.width, decoder.screen.height),
+      (WIDTH, HEIGHT)
+    );
+    assert_eq!(decoder.screen.palette.as_deref(), Some(&palette[..]));
+
+    let canvas = decoder.next_frame().unwrap().unwrap().to_vec();
+    // Read along with the first image
+    assert_eq!(decoder.repeat, Some(0));
+    for (px, &i) in canvas.chunks_exact(4).zip(&first) {
+      assert_eq!(px[..3], palette[usize::from(i) * 3..][..3]);
+      assert_eq!(px[3], 255);
+    }
+    let canvas = decoder.next_frame().unwrap().unwrap();
+    for (j, &i) in second.iter().enumerate() {
+      let (x, y) = (10 + j % 40, 20 + j / 40);
+      let px = &canvas[(y * usize::from(WIDTH) + x) * 4..][..4];
+      let expected = match i {
+        3 => &palette[usize::from(first[y * usize::from(WIDTH) + x]) * 3..][..3],
+        i => &local[usize::from(i) * 3..][..3],
+      };
+      assert_eq!(&px[..3], expected);
+    }
+    let image = decoder.image();
+    assert_eq!(image.control.delay, 7);
+    assert_eq!(image.control.disposal, Disposal::Background);
+    assert_eq!(image.indices, second);
+    assert!(decoder.next_frame().unwrap().is_none());
+  }
+
+  #[test]
+  fn encodes_deterministically() {
+    // From RGBA, so the quantization is covered too
+    let rgba = super::super::noise(usize::from(WIDTH) * usize::from(HEIGHT) * 4, 4);
+    let encoded = (0..3)
+      .map(|_| {
+        let mut histogram = Histogram::new(128);
+        histogram.add(&rgba);
+        let mut mapper = histogram.mapper(256);
+        let mut indices = Vec::new();
+        mapper.remap(
+          &rgba,
+          usize::from(WIDTH),
+          crate::gif::Dither::FloydSteinberg,
+          &mut indices,
+        );
+        encode(
+          mapper.palette(),
+          &[Frame {
+            transparent: mapper.transparent(),
+            ..full(&indices)
+          }],
+        )
+      })
+      .collect::<Vec<_>>();
+    assert!(encoded.iter().all(|v| *v == encoded[0]));
+  }
+}
@@ -0,0 +1,402 @@
+use std::borrow::Cow;
+
+use crate::Result;
+
+const MAX_CODES: usize = 4096;
+const MAX_WIDTH: u8 = 12;
+
+pub(crate) struct Decoder {
+  prefix: Vec<u16>,
+  suffix: Vec<u8>,
+  stack: Vec<u8>,
+}
+
+impl Default for Decoder {
+  fn default() -> Self {
+    Self {
+      prefix: vec![0_u16; MAX_CODES],
+      suffix: vec![0_u8; MAX_CODES],
+      stack: Vec::with_capacity(MAX_CODES),
+    }
+  }
+}
+
+impl Decoder {
+  /// Decodes at most `limit` indices of the `data` code stream into `out`.
+  /// A stream cut short leaves `out` shorter than `limit`
+  pub(crate) fn decode(
+    &mut self,
+    min_code_size: u8,
+    data: &[u8],
+    limit: usize,
+    out: &mut Vec<u8>,
+  ) -> Result<()> {
+    if !(1..MAX_WIDTH).contains(&min_code_size) {
+      return Err(Cow::Owned(format!(
+        "Invalid LZW minimum code size {min_code_size}"
+      )));
+    }
+
+    let clear = 1_u16 << min_code_size;
+    let end = clear + 1;
+    let mut next = end + 1;
+    let mut width = min_code_size + 1;
+    let mut prev: Option<u16> = None;
+
+    let mut acc = 0_u32;
+    let mut bits = 0_u8;
+    let mut data = data.iter();
+
+    while out.len() < limit {
+      while bits < width {
+        let Some(&b) = data.next() else {
+          return Ok(());
+        };
+        acc |= u32::from(b) << bits;
+        bits += 8;
+      }
+      #[allow(clippy::cast_possible_truncation)]
+      let code = (acc & ((1_u32 << width) - 1)) as u16;
+      acc >>= width;
+      bits -= width;
+
+      if code == clear {
+        next = end + 1;
+        width = min_code_size + 1;
+        prev = None;
+        continue;
+      }
+      if code == end {
+        break;
+      }
+
+      let Some(p) = prev else {
+        if code > clear {
+          return Err(Cow::Borrowed("Corrupted LZW stream"));
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        out.push(code as u8);
+        prev = Some(code);
+        continue;
+      };
+
+      let first = match code.cmp(&next) {
+        std::cmp::Ordering::Less => self.emit(code, clear, out),
+        std::cmp::Ordering::Equal => {
+          let first = self.emit(p, clear, out);
+          out.push(first);
+          first
+        }
+        std::cmp::Ordering::Greater => return Err(Cow::Borrowed("Corrupted LZW stream")),
+      };
+
+      if usize::from(next) < MAX_CODES {
+        self.prefix[usize::from(next)] = p;
+        self.suffix[usize::from(next)] = first;
+        next += 1;
+        if next == 1 << width && width < MAX_WIDTH {
+          width += 1;
+        }
+      }
+      prev = Some(code);
+    }
+
+    out.truncate(limit);
+    Ok(())
+  }
+
+  /// Appends the string of `code` and returns its first index
+  fn emit(&mut self, mut code: u16, clear: u16, out: &mut Vec<u8>) -> u8 {
+    self.stack.clear();
+    while code > clear {
+      self.stack.push(self.suffix[usize::from(code)]);
+      code = self.prefix[usize::from(code)];
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let first = code as u8;
+    out.push(first);
+    out.extend(self.stack.iter().rev());
+    first
+  }
+}
+
+const NONE: u16 = u16::MAX;
+
+/// Lets the encoder extend a string with an index whose color is near the
+/// actual one, trading accuracy for longer strings
+pub(crate) struct Lossy<'a> {
+  /// RGB triples the indices refer to
+  pub(crate) colors: &'a [u8],
+  pub(crate) transparent: Option<u8>,
+  /// The maximum squared RGB distance of the replaced colors
+  pub(crate) distance: u32,
+}
+
+impl Lossy<'_> {
+  fn distance(&self, a: u8, b: u8) -> Option<u32> {
+    if self.transparent == Some(a) || self.transparent == Some(b) {
+      return None;
+    }
+    let a = self
+      .colors
+      .get(usize::from(a) * 3..usize::from(a) * 3 + 3)?;
+    let b = self
+      .colors
+      .get(usize::from(b) * 3..usize::from(b) * 3 + 3)?;
+    let d = a
+      .iter()
+      .zip(b)
+      .map(|(&a, &b)| u32::from(a.abs_diff(b)).pow(2))
+      .sum::<u32>();
+    Some(d).filter(|&d| d <= self.distance)
+  }
+}
+
+pub(crate) struct Encoder {
+  child: Vec<u16>,
+  sibling: Vec<u16>,
+  suffix: Vec<u8>,
+}
+
+impl Default for Encoder {
+  fn default() -> Self {
+    Self {
+      child: vec![NONE; MAX_CODES],
+      sibling: vec![NONE; MAX_CODES],
+      suffix: vec![0_u8; MAX_CODES],
+    }
+  }
+}
+
+struct BitWriter<'a> {
+  out: &'a mut Vec<u8>,
+  acc: u32,
+  bits: u8,
+}
+
+impl BitWriter<'_> {
+  fn write(&mut self, code: u16, width: u8) {
+    self.acc |= u32::from(code) << self.bits;
+    self.bits += width;
+    while self.bits >= 8 {
+      #[allow(clippy::cast_possible_truncation)]
+      self.out.push(self.acc as u8);
+      self.acc >>= 8;
+      self.bits -= 8;
+    }
+  }
+
+  fn flush(&mut self) {
+    if self.bits > 0 {
+      #[allow(clippy::cast_possible_truncation)]
+      self.out.push(self.acc as u8);
+      self.acc = 0;
+      self.bits = 0;
+    }
+  }
+}
+
+impl Encoder {
+  /// Compresses `indices` into a code stream appended to `out`. Every index
+  /// must fit into `min_code_size` bits. With `lossy` set, the indices are
+  /// not reproduced exactly
+  pub(crate) fn encode(
+    &mut self,
+    min_code_size: u8,
+    indices: &[u8],
+    lossy: Option<&Lossy>,
+    out: &mut Vec<u8>,
+  ) {
+    let clear = 1_u16 << min_code_size;
+    let end = clear + 1;
+    let mut next = end + 1;
+    let mut width = min_code_size + 1;
+    let mut writer = BitWriter {
+      out,
+      acc: 0,
+      bits: 0,
+    };
+
+    writer.write(clear, width);
+    self.child[..usize::from(clear)].fill(NONE);
+
+    let mut indices = indices.iter();
+    if let Some(&first) = indices.next() {
+      let mut cur = u16::from(first);
+      for &b in indices {
+        let found = self
+          .find(cur, b)
+          .or_else(|| lossy.and_then(|v| self.find_near(cur, b, v)));
+        if let Some(code) = found {
+          cur = code;
+          continue;
+        }
+
+        writer.write(cur, width);
+        if usize::from(next) < MAX_CODES {
+          self.add(next, cur, b);
+          next += 1;
+          if next > 1 << width && width < MAX_WIDTH {
+            width += 1;
+          }
+        } else {
+          writer.write(clear, width);
+          self.child[..usize::from(clear)].fill(NONE);
+          next = end + 1;
+          width = min_code_size + 1;
+        }
+        cur = u16::from(b);
+      }
+      writer.write(cur, width);
+    }
+
+    writer.write(end, width);
+    writer.flush();
+  }
+
+  fn find(&self, prefix: u16, b: u8) -> Option<u16> {
+    let mut code = self.child[usize::from(prefix)];
+    while code != NONE {
+      if self.suffix[usize::from(code)] == b {
+        return Some(code);
+      }
+      code = self.sibling[usize::from(code)];
+    }
+    None
+  }
+
+  /// The child of `prefix` whose color is the nearest to `b` within the
+  /// `lossy` distance
+  fn find_near(&self, prefix: u16, b: u8, lossy: &Lossy) -> Option<u16> {
+    let mut best: Option<(u32, u16)> = None;
+    let mut code = self.child[usize::from(prefix)];
+    while code != NONE {
+      if let Some(d) = lossy.distance(self.suffix[usize::from(code)], b) {
+        if best.is_none_or(|(v, _)| v > d) {
+          best = Some((d, code));
+        }
+      }
+      code = self.sibling[usize::from(code)];
+    }
+    best.map(|(_, code)| code)
+  }
+
+  fn add(&mut self, code: u16, prefix: u16, b: u8) {
+    self.suffix[usize::from(code)] = b;
+    self.child[usize::from(code)] = NONE;
+    self.sibling[usize::from(code)] = self.child[usize::from(prefix)];
+    self.child[usize::from(prefix)] = code;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn round_trip(min_code_size: u8, indices: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    Encoder::default().encode(min_code_size, indices, None, &mut data);
+    let mut out = Vec::new();
+    Decoder::default()
+      .decode(min_code_size, &data, indices.len(), &mut out)
+      .unwrap();
+    assert_eq!(out, indices);
+    data
+  }
+
+  #[test]
+  fn round_trips() {
+    round_trip(2, &[0, 1, 2, 3, 3, 3, 3, 2, 1, 0, 0, 1, 2, 3]);
+    round_trip(8, b"TOBEORNOTTOBEORTOBEORNOT");
+    round_trip(8, &[]);
+  }
+
+  #[test]
+  fn resets_the_full_table() {
+    // Noise adds a code for almost every index, filling the table a few
+    // times over and going through every code width
+    let indices = super::super::noise(20_000, 1);
+    let data = round_trip(8, &indices);
+    assert!(data.len() > 20_000);
+
+    let indices = super::super::noise(50_000, 2)
+      .into_iter()
+      .map(|v| v & 3)
+      .collect::<Vec<_>>();
+    round_trip(2, &indices);
+  }
+
+  #[test]
+  fn encodes_deterministically() {
+    let indices = super::super::noise(10_000, 3);
+    let mut encoder = Encoder::default();
+    let mut first = Vec::new();
+    encoder.encode(8, &indices, None, &mut first);
+    // Reused, after a table left filled by the previous frame
+    let mut second = Vec::new();
+    encoder.encode(8, &indices, None, &mut second);
+    let mut third = Vec::new();
+    Encoder::default().encode(8, &indices, None, &mut third);
+    assert_eq!(first, second);
+    assert_eq!(first, third);
+  }
+
+  /// A smooth palette, so neighbouring indices have near colors. The last
+  /// index is transparent
+  fn colors() -> Vec<u8> {
+    (0..=255_u8).flat_map(|i| [i, i / 2, 255 - i]).collect()
+  }
+
+  fn encode_lossy(indices: &[u8], colors: &[u8], lossy: u8) -> Vec<u8> {
+    let lossy = Lossy {
+      colors,
+      transparent: Some(255),
+      distance: u32::from(lossy).pow(2),
+    };
+    let mut data = Vec::new();
+    Encoder::default().encode(8, indices, Some(&lossy), &mut data);
+    data
+  }
+
+  #[test]
+  fn lossless_at_zero_lossy() {
+    let indices = super::super::noise(20_000, 4);
+    let mut lossless = Vec::new();
+    Encoder::default().encode(8, &indices, None, &mut lossless);
+    assert_eq!(encode_lossy(&indices, &colors(), 0), lossless);
+  }
+
+  #[test]
+  fn shrinks_with_lossy() {
+    let colors = colors();
+    let indices = super::super::noise(20_000, 5);
+    let sizes =
+      [0, 4, 8, 16, 32, 64, 128].map(|lossy| encode_lossy(&indices, &colors, lossy).len());
+    assert!(sizes.windows(2).all(|v| v[1] < v[0]), "{sizes:?}");
+  }
+
+  #[test]
+  fn keeps_lossy_colors_within_the_bound() {
+    let colors = colors();
+    let indices = super::super::noise(20_000, 6);
+    for lossy in [4, 32, 128] {
+      let data = encode_lossy(&indices, &colors, lossy);
+      let mut out = Vec::new();
+      Decoder::default()
+        .decode(8, &data, indices.len(), &mut out)
+        .unwrap();
+      assert_eq!(out.len(), indices.len());
+      for (&a, &b) in indices.iter().zip(&out) {
+        // Transparent pixels are never replaced
+        assert_eq!(a == 255, b == 255);
+        let (a, b) = (
+          &colors[usize::from(a) * 3..][..3],
+          &colors[usize::from(b) * 3..][..3],
+        );
+        for (a, b) in a.iter().zip(b) {
+          assert!(a.abs_diff(*b) <= lossy);
+        }
+      }
+    }
+  }
+}
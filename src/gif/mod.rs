@@ -0,0 +1,78 @@
+mod decode;
+mod encode;
+mod lzw;
+mod optimize;
+mod quantize;
+mod resize;
+
+pub(crate) use decode::*;
+pub(crate) use encode::*;
+pub(crate) use optimize::*;
+pub(crate) use quantize::*;
+pub(crate) use resize::*;
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub(crate) enum Disposal {
+  #[default]
+  Unspecified,
+  Keep,
+  Background,
+  Previous,
+}
+
+impl Disposal {
+  pub(crate) fn from_bits(v: u8) -> Self {
+    match v {
+      1 => Self::Keep,
+      2 => Self::Background,
+      3 => Self::Previous,
+      _ => Self::Unspecified,
+    }
+  }
+
+  pub(crate) fn as_str(self) -> &'static str {
+    match self {
+      Self::Unspecified => "unspecified",
+      Self::Keep => "keep",
+      Self::Background => "background",
+      Self::Previous => "previous",
+    }
+  }
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub(crate) struct Rect {
+  pub(crate) left: u16,
+  pub(crate) top: u16,
+  pub(crate) width: u16,
+  pub(crate) height: u16,
+}
+
+impl Rect {
+  /// Smallest rectangle covering both
+  pub(crate) fn union(self, other: Self) -> Self {
+    let left = self.left.min(other.left);
+    let top = self.top.min(other.top);
+    let right = (self.left + self.width).max(other.left + other.width);
+    let bottom = (self.top + self.height).max(other.top + other.height);
+    Self {
+      left,
+      top,
+      width: right - left,
+      height: bottom - top,
+    }
+  }
+}
+
+/// Deterministic pseudo-random bytes (xorshift) for the tests
+#[cfg(test)]
+pub(crate) fn noise(len: usize, mut seed: u32) -> Vec<u8> {
+  (0..len)
+    .map(|_| {
+      seed ^= seed << 13;
+      seed ^= seed >> 17;
+      seed ^= seed << 5;
+      seed.to_le_bytes()[0]
+    })
+    .collect()
+}
@@ -0,0 +1,189 @@
+use std::io::Write;
+
+use crate::Result;
+
+use super::{Disposal, Encoder, Frame, Rect};
+
+/// A frame held back until the next one decides its disposal
+struct Held {
+  rect: Rect,
+  delay: u16,
+  transparent: Option<u8>,
+  palette: Option<Vec<u8>>,
+  indices: Vec<u8>,
+}
+
+impl Held {
+  /// Grows the frame to cover `rect` with transparent pixels
+  fn extend(&mut self, rect: Rect) {
+    let union = self.rect.union(rect);
+    let Some(transparent) = self.transparent.filter(|_| union != self.rect) else {
+      return;
+    };
+    let width = usize::from(union.width);
+    let mut indices = vec![transparent; width * usize::from(union.height)];
+    let dx = usize::from(self.rect.left - union.left);
+    let dy = usize::from(self.rect.top - union.top);
+    for (y, row) in self
+      .indices
+      .chunks_exact(usize::from(self.rect.width))
+      .enumerate()
+    {
+      indices[(dy + y) * width + dx..][..row.len()].copy_from_slice(row);
+    }
+    self.rect = union;
+    self.indices = indices;
+  }
+}
+
+/// Crops full-canvas frames to the area changed since the previous one,
+/// marks unchanged pixels transparent and picks the disposal methods
+///
+/// Frames are held back by one, as the disposal of a frame depends on the
+/// next one
+pub(crate) struct Optimizer {
+  width: usize,
+  height: usize,
+  /// RGBA of what is displayed once the held frame is drawn
+  canvas: Vec<u8>,
+  target: Vec<u8>,
+  held: Option<Held>,
+}
+
+impl Optimizer {
+  pub(crate) fn new(width: u16, height: u16) -> Self {
+    let (width, height) = (usize::from(width), usize::from(height));
+    Self {
+      width,
+      height,
+      canvas: vec![0_u8; width * height * 4],
+      target: Vec::new(),
+      held: None,
+    }
+  }
+
+  /// Smallest rectangle holding every pixel matching `f`
+  fn bounds(&self, f: impl Fn(usize) -> bool) -> Option<Rect> {
+    let mut bounds: Option<(usize, usize, usize, usize)> = None;
+    for y in 0..self.height {
+      for x in 0..self.width {
+        if f(y * self.width + x) {
+          let b = bounds.get_or_insert((x, y, x, y));
+          *b = (b.0.min(x), b.1, b.2.max(x), y);
+        }
+      }
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    bounds.map(|(left, top, right, bottom)| Rect {
+      left: left as u16,
+      top: top as u16,
+      width: (right - left + 1) as u16,
+      height: (bottom - top + 1) as u16,
+    })
+  }
+
+  fn differs(&self, p: usize) -> bool {
+    let (a, b) = (&self.target[p * 4..][..4], &self.canvas[p * 4..][..4]);
+    (a[3] != 0 || b[3] != 0) && a != b
+  }
+
+  /// Queues a full-canvas frame, writing the held one. `colors` is the
+  /// color table the frame indices refer to
+  pub(crate) fn push<W: Write>(
+    &mut self,
+    encoder: &mut Encoder<W>,
+    frame: &Frame,
+    colors: &[u8],
+  ) -> Result<()> {
+    let first = self.held.is_none();
+    self.target.clear();
+    for &i in frame.indices {
+      if frame.transparent == Some(i) {
+        self.target.extend_from_slice(&[0, 0, 0, 0]);
+      } else {
+        let c = colors
+          .get(usize::from(i) * 3..usize::from(i) * 3 + 3)
+          .unwrap_or(&[0, 0, 0]);
+        self.target.extend_from_slice(&[c[0], c[1], c[2], 255]);
+      }
+    }
+
+    if let Some(mut held) = self.held.take() {
+      // Opaque pixels turning transparent are only cleared by disposing the
+      // held frame to the background
+      let clear = self.bounds(|p| self.target[p * 4 + 3] == 0 && self.canvas[p * 4 + 3] != 0);
+      let disposal = match clear {
+        Some(rect) => {
+          held.extend(rect);
+          let r = held.rect;
+          for y in usize::from(r.top)..usize::from(r.top) + usize::from(r.height) {
+            let start = (y * self.width + usize::from(r.left)) * 4;
+            self.canvas[start..start + usize::from(r.width) * 4].fill(0);
+          }
+          Disposal::Background
+        }
+        None => Disposal::Keep,
+      };
+      write_held(encoder, &held, disposal)?;
+    }
+
+    let full = Rect {
+      left: 0,
+      top: 0,
+      width: frame.rect.width,
+      height: frame.rect.height,
+    };
+    // Without a transparent index unchanged pixels cannot be skipped, and
+    // the first frame is kept whole for the decoders not clearing the
+    // canvas on loop
+    let rect = match frame.transparent {
+      Some(_) if !first => self.bounds(|p| self.differs(p)).unwrap_or(Rect {
+        left: 0,
+        top: 0,
+        width: 1,
+        height: 1,
+      }),
+      _ => full,
+    };
+
+    let mut indices = Vec::with_capacity(usize::from(rect.width) * usize::from(rect.height));
+    for y in usize::from(rect.top)..usize::from(rect.top) + usize::from(rect.height) {
+      for x in usize::from(rect.left)..usize::from(rect.left) + usize::from(rect.width) {
+        let p = y * self.width + x;
+        match frame.transparent {
+          Some(t) if !self.differs(p) => indices.push(t),
+          _ => indices.push(frame.indices[p]),
+        }
+      }
+    }
+
+    self.canvas.copy_from_slice(&self.target);
+    self.held = Some(Held {
+      rect,
+      delay: frame.delay,
+      transparent: frame.transparent,
+      palette: frame.palette.map(<[u8]>::to_vec),
+      indices,
+    });
+    Ok(())
+  }
+
+  /// Writes the held frame, if any
+  pub(crate) fn flush<W: Write>(&mut self, encoder: &mut Encoder<W>) -> Result<()> {
+    match self.held.take() {
+      Some(held) => write_held(encoder, &held, Disposal::Keep),
+      None => Ok(()),
+    }
+  }
+}
+
+fn write_held<W: Write>(encoder: &mut Encoder<W>, held: &Held, disposal: Disposal) -> Result<()> {
+  encoder.write_frame(&Frame {
+    rect: held.rect,
+    delay: held.delay,
+    disposal,
+    transparent: held.transparent,
+    palette: held.palette.as_deref(),
+    indices: &held.indices,
+  })
+}
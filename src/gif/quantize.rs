@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+
+/// Color occurrence counts accumulated over one or more RGBA frames.
+/// Pixels with alpha under the threshold are not counted, but mark the
+/// histogram as transparent
+#[derive(Default)]
+pub(crate) struct Histogram {
+  counts: HashMap<u32, u32>,
+  alpha_threshold: u8,
+  transparent: bool,
+}
+
+struct Entry {
+  rgb: [u8; 3],
+  count: u32,
+}
+
+struct Bucket {
+  start: usize,
+  end: usize,
+  count: u64,
+  /// Channel with the widest range and the range itself
+  axis: usize,
+  range: u8,
+}
+
+impl Bucket {
+  fn new(entries: &[Entry], start: usize, end: usize) -> Self {
+    let mut min = [u8::MAX; 3];
+    let mut max = [u8::MIN; 3];
+    let mut count = 0_u64;
+    for e in &entries[start..end] {
+      for c in 0..3 {
+        min[c] = min[c].min(e.rgb[c]);
+        max[c] = max[c].max(e.rgb[c]);
+      }
+      count += u64::from(e.count);
+    }
+    let (axis, range) = (0..3)
+      .map(|c| (c, max[c].saturating_sub(min[c])))
+      .max_by_key(|&(c, range)| (range, 2 - c))
+      .unwrap_or_default();
+    Self {
+      start,
+      end,
+      count,
+      axis,
+      range,
+    }
+  }
+}
+
+impl Histogram {
+  pub(crate) fn new(alpha_threshold: u8) -> Self {
+    Self {
+      alpha_threshold,
+      ..Self::default()
+    }
+  }
+
+  pub(crate) fn add(&mut self, rgba: &[u8]) {
+    for px in rgba.chunks_exact(4) {
+      self.count(px);
+    }
+  }
+
+  /// Counts only the pixels of `rgba` that differ from `previous`
+  pub(crate) fn add_changed(&mut self, rgba: &[u8], previous: &[u8]) {
+    for (px, prev) in rgba.chunks_exact(4).zip(previous.chunks_exact(4)) {
+      if px != prev {
+        self.count(px);
+      }
+    }
+  }
+
+  fn count(&mut self, px: &[u8]) {
+    if px[3] < self.alpha_threshold {
+      self.transparent = true;
+      return;
+    }
+    let key = u32::from_be_bytes([0, px[0], px[1], px[2]]);
+    *self.counts.entry(key).or_default() += 1;
+  }
+
+  /// Builds a palette of at most `max_colors` RGB triples using median cut
+  pub(crate) fn palette(&self, max_colors: usize) -> Vec<u8> {
+    let mut entries = self
+      .counts
+      .iter()
+      .map(|(&k, &count)| {
+        let [_, r, g, b] = k.to_be_bytes();
+        Entry {
+          rgb: [r, g, b],
+          count,
+        }
+      })
+      .collect::<Vec<_>>();
+    entries.sort_unstable_by_key(|e| e.rgb);
+
+    let mut buckets = vec![Bucket::new(&entries, 0, entries.len())];
+    while buckets.len() < max_colors {
+      let Some((i, _)) = buckets
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| b.end - b.start > 1 && b.range > 0)
+        .max_by_key(|(i, b)| (b.count * u64::from(b.range), usize::MAX - i))
+      else {
+        break;
+      };
+
+      let bucket = buckets.swap_remove(i);
+      let axis = bucket.axis;
+      let slice = &mut entries[bucket.start..bucket.end];
+      slice.sort_unstable_by_key(|e| (e.rgb[axis], e.rgb));
+
+      let half = bucket.count.div_ceil(2);
+      let mut acc = 0_u64;
+      let mut mid = bucket.start + 1;
+      for (j, e) in slice.iter().enumerate() {
+        acc += u64::from(e.count);
+        if acc >= half {
+          mid = bucket.start + (j + 1).clamp(1, slice.len() - 1);
+          break;
+        }
+      }
+
+      buckets.push(Bucket::new(&entries, bucket.start, mid));
+      buckets.push(Bucket::new(&entries, mid, bucket.end));
+    }
+
+    buckets.sort_unstable_by_key(|b| b.start);
+    let mut palette = Vec::with_capacity(buckets.len() * 3);
+    for bucket in buckets.iter().filter(|b| b.count > 0) {
+      let mut sum = [0_u64; 3];
+      for e in &entries[bucket.start..bucket.end] {
+        for (s, c) in sum.iter_mut().zip(e.rgb) {
+          *s += u64::from(c) * u64::from(e.count);
+        }
+      }
+      for s in sum {
+        #[allow(clippy::cast_possible_truncation)]
+        palette.push(((s + bucket.count / 2) / bucket.count) as u8);
+      }
+    }
+    if palette.is_empty() {
+      palette.extend_from_slice(&[0, 0, 0]);
+    }
+    palette
+  }
+
+  /// Reserves the transparent index even if no transparent pixel is
+  /// counted
+  pub(crate) fn reserve_transparent(&mut self) {
+    self.transparent = true;
+  }
+
+  /// Builds a mapper onto a palette of at most `max_colors` (at least 2).
+  /// The last index is reserved for transparent pixels if any were counted
+  pub(crate) fn mapper(&self, max_colors: usize) -> Mapper {
+    if !self.transparent {
+      return Mapper::new(self.palette(max_colors), None, self.alpha_threshold);
+    }
+    let mut palette = self.palette(max_colors - 1);
+    #[allow(clippy::cast_possible_truncation)]
+    let transparent = (palette.len() / 3) as u8;
+    palette.extend_from_slice(&[0, 0, 0]);
+    Mapper::new(palette, Some(transparent), self.alpha_threshold)
+  }
+}
+
+/// Dithering applied while mapping frames onto a palette
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Dither {
+  None,
+  /// Ordered dithering. Lower scales produce a more visible pattern
+  Bayer(u8),
+  FloydSteinberg,
+  /// Sierra Lite (`sierra2_4a` in `ffmpeg`)
+  Sierra,
+}
+
+impl Default for Dither {
+  fn default() -> Self {
+    Self::Bayer(2)
+  }
+}
+
+/// Error diffusion kernel as `(dx, dy, weight)` entries and the divisor
+const FLOYD_STEINBERG: (&[(isize, usize, i32)], i32) =
+  (&[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)], 16);
+const SIERRA_LITE: (&[(isize, usize, i32)], i32) = (&[(1, 0, 2), (-1, 1, 1), (0, 1, 1)], 4);
+
+/// Nearest palette color lookup with a per-color cache. Pixels with alpha
+/// under the threshold map to the transparent index, if any
+pub(crate) struct Mapper {
+  palette: Vec<u8>,
+  transparent: Option<u8>,
+  alpha_threshold: u8,
+  cache: HashMap<u32, u8>,
+}
+
+impl Mapper {
+  pub(crate) fn new(palette: Vec<u8>, transparent: Option<u8>, alpha_threshold: u8) -> Self {
+    Self {
+      palette,
+      transparent,
+      alpha_threshold,
+      cache: HashMap::new(),
+    }
+  }
+
+  pub(crate) fn palette(&self) -> &[u8] {
+    &self.palette
+  }
+
+  pub(crate) fn transparent(&self) -> Option<u8> {
+    self.transparent
+  }
+
+  /// The transparent index if the `px` alpha is under the threshold
+  fn transparent_at(&self, px: &[u8]) -> Option<u8> {
+    self.transparent.filter(|_| px[3] < self.alpha_threshold)
+  }
+
+  pub(crate) fn index(&mut self, rgb: [u8; 3]) -> u8 {
+    let key = u32::from_be_bytes([0, rgb[0], rgb[1], rgb[2]]);
+    if let Some(&i) = self.cache.get(&key) {
+      return i;
+    }
+
+    let mut best = (u32::MAX, 0_u8);
+    for (i, color) in self.palette.chunks_exact(3).enumerate() {
+      if self.transparent.is_some_and(|v| usize::from(v) == i) {
+        continue;
+      }
+      let d = (0..3)
+        .map(|c| u32::from(rgb[c].abs_diff(color[c])).pow(2))
+        .sum::<u32>();
+      if d < best.0 {
+        #[allow(clippy::cast_possible_truncation)]
+        let i = i as u8;
+        best = (d, i);
+      }
+    }
+    self.cache.insert(key, best.1);
+    best.1
+  }
+
+  /// Mean distance between the opaque `rgba` pixels and their nearest
+  /// palette colors
+  pub(crate) fn error(&mut self, rgba: &[u8]) -> f64 {
+    let mut sum = 0_f64;
+    let mut count = 0_usize;
+    for px in rgba.chunks_exact(4) {
+      if px[3] < self.alpha_threshold {
+        continue;
+      }
+      count += 1;
+      let rgb = [px[0], px[1], px[2]];
+      let i = usize::from(self.index(rgb)) * 3;
+      let d = (0..3)
+        .map(|c| u32::from(rgb[c].abs_diff(self.palette[i + c])).pow(2))
+        .sum::<u32>();
+      sum += f64::from(d).sqrt();
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let mean = sum / count.max(1) as f64;
+    mean
+  }
+
+  /// Maps `rgba` onto the palette applying the given dithering
+  pub(crate) fn remap(&mut self, rgba: &[u8], width: usize, dither: Dither, out: &mut Vec<u8>) {
+    match dither {
+      Dither::None => {
+        out.clear();
+        out.resize(rgba.len() / 4, 0);
+        self.map_into(rgba, out);
+      }
+      Dither::Bayer(scale) => self.remap_bayer(rgba, width, scale, out),
+      Dither::FloydSteinberg => self.remap_diffuse(rgba, width, FLOYD_STEINBERG, out),
+      Dither::Sierra => self.remap_diffuse(rgba, width, SIERRA_LITE, out),
+    }
+  }
+
+  /// Maps `rgba` onto the palette without dithering, into `out` of one
+  /// byte per pixel
+  pub(crate) fn map_into(&mut self, rgba: &[u8], out: &mut [u8]) {
+    for (px, i) in rgba.chunks_exact(4).zip(out) {
+      *i = match self.transparent_at(px) {
+        Some(v) => v,
+        None => self.index([px[0], px[1], px[2]]),
+      };
+    }
+  }
+
+  /// Maps `rgba` onto the palette applying ordered (bayer) dithering of the
+  /// given scale
+  fn remap_bayer(&mut self, rgba: &[u8], width: usize, scale: u8, out: &mut Vec<u8>) {
+    let delta = 1_i16 << (5 - scale);
+    out.clear();
+    for (i, px) in rgba.chunks_exact(4).enumerate() {
+      if let Some(v) = self.transparent_at(px) {
+        out.push(v);
+        continue;
+      }
+      let (x, y) = (i % width, i / width);
+      let d = (i16::from(bayer((y & 7) << 3 | (x & 7))) >> scale) - delta;
+      #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+      let rgb = [0, 1, 2].map(|c| (i16::from(px[c]) + d).clamp(0, 255) as u8);
+      out.push(self.index(rgb));
+    }
+  }
+
+  /// Maps `rgba` onto the palette diffusing the quantization error over the
+  /// neighbouring pixels
+  fn remap_diffuse(
+    &mut self,
+    rgba: &[u8],
+    width: usize,
+    (kernel, divisor): (&[(isize, usize, i32)], i32),
+    out: &mut Vec<u8>,
+  ) {
+    out.clear();
+    if width == 0 {
+      return;
+    }
+    let height = rgba.len() / 4 / width;
+    // Errors of the current and the next row, scaled by the divisor
+    let mut errors = vec![[0_i32; 3]; width * 2];
+    for y in 0..height {
+      let next = (y + 1) % 2 * width;
+      errors[next..next + width].fill([0; 3]);
+      for x in 0..width {
+        let px = &rgba[(y * width + x) * 4..][..4];
+        if let Some(v) = self.transparent_at(px) {
+          out.push(v);
+          continue;
+        }
+        let e = errors[y % 2 * width + x];
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let rgb = [0, 1, 2].map(|c| (i32::from(px[c]) + e[c] / divisor).clamp(0, 255) as u8);
+        let i = self.index(rgb);
+        out.push(i);
+
+        let color = &self.palette[usize::from(i) * 3..][..3];
+        let err = [0, 1, 2].map(|c| i32::from(rgb[c]) - i32::from(color[c]));
+        for &(dx, dy, weight) in kernel {
+          let Some(nx) = x.checked_add_signed(dx).filter(|&v| v < width) else {
+            continue;
+          };
+          if y + dy >= height {
+            continue;
+          }
+          let e = &mut errors[(y + dy) % 2 * width + nx];
+          for c in 0..3 {
+            e[c] += err[c] * weight;
+          }
+        }
+      }
+    }
+  }
+}
+
+/// 8x8 bayer matrix value for the position `p` (`y << 3 | x`)
+fn bayer(p: usize) -> u8 {
+  let q = p ^ (p >> 3);
+  #[allow(clippy::cast_possible_truncation)]
+  let v =
+    ((p & 4) >> 2 | (q & 4) >> 1 | (p & 2) << 1 | (q & 2) << 2 | (p & 1) << 4 | (q & 1) << 5) as u8;
+  v
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Opaque RGBA pixels of noise
+  fn rgba(len: usize, seed: u32) -> Vec<u8> {
+    let mut rgba = super::super::noise(len * 4, seed);
+    for px in rgba.chunks_exact_mut(4) {
+      px[3] = 255;
+    }
+    rgba
+  }
+
+  #[test]
+  fn keeps_up_to_256_colors_exact() {
+    // Every color once, in a few different counts
+    let colors = (0..=255_u8)
+      .flat_map(|i| [i, i.wrapping_mul(7), 255 - i, 255])
+      .collect::<Vec<_>>();
+    let mut histogram = Histogram::new(128);
+    for n in 0..3 {
+      histogram.add(&colors[n * 256..]);
+    }
+    let mut mapper = histogram.mapper(256);
+    assert_eq!(mapper.palette().len(), 256 * 3);
+    assert_eq!(mapper.transparent(), None);
+
+    let mut indices = Vec::new();
+    mapper.remap(&colors, 256, Dither::None, &mut indices);
+    for (px, &i) in colors.chunks_exact(4).zip(&indices) {
+      assert_eq!(&mapper.palette()[usize::from(i) * 3..][..3], &px[..3]);
+    }
+  }
+
+  #[test]
+  fn reserves_the_transparent_index() {
+    let mut histogram = Histogram::new(128);
+    histogram.add(&[10, 20, 30, 255, 0, 0, 0, 0, 40, 50, 60, 255]);
+    let mut mapper = histogram.mapper(4);
+    assert_eq!(mapper.transparent(), Some(2));
+    let mut indices = Vec::new();
+    mapper.remap(
+      &[40, 50, 60, 255, 1, 1, 1, 10],
+      2,
+      Dither::None,
+      &mut indices,
+    );
+    assert_eq!(indices, [1, 2]);
+  }
+
+  #[test]
+  fn quantizes_deterministically() {
+    // Every histogram iterates its colors in a different order
+    let rgba = rgba(64 * 64, 1);
+    let palettes = (0..4)
+      .map(|_| {
+        let mut histogram = Histogram::new(128);
+        histogram.add(&rgba);
+        histogram.palette(200)
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(palettes[0].len(), 200 * 3);
+    assert!(palettes.iter().all(|v| *v == palettes[0]));
+
+    for dither in [
+      Dither::None,
+      Dither::Bayer(2),
+      Dither::FloydSteinberg,
+      Dither::Sierra,
+    ] {
+      let mut first = Vec::new();
+      Mapper::new(palettes[0].clone(), None, 128).remap(&rgba, 64, dither, &mut first);
+      let mut second = Vec::new();
+      Mapper::new(palettes[1].clone(), None, 128).remap(&rgba, 64, dither, &mut second);
+      assert_eq!(first, second);
+    }
+  }
+}
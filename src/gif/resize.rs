@@ -0,0 +1,136 @@
+use std::f32::consts::PI;
+
+struct Contribution {
+  start: usize,
+  weights: Vec<f32>,
+}
+
+/// Resampling filter
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub(crate) enum Filter {
+  #[default]
+  Lanczos,
+  Bilinear,
+  Nearest,
+}
+
+impl Filter {
+  /// Kernel radius in source pixels, when not downscaling
+  fn support(self) -> f32 {
+    match self {
+      Self::Lanczos => 3_f32,
+      Self::Bilinear => 1_f32,
+      Self::Nearest => 0.5_f32,
+    }
+  }
+
+  fn weight(self, x: f32) -> f32 {
+    match self {
+      Self::Lanczos => lanczos(x),
+      Self::Bilinear => (1_f32 - x.abs()).max(0_f32),
+      Self::Nearest => 1_f32,
+    }
+  }
+}
+
+fn lanczos(x: f32) -> f32 {
+  const LOBES: f32 = 3_f32;
+  if x == 0_f32 {
+    1_f32
+  } else if x.abs() < LOBES {
+    let x = x * PI;
+    LOBES * x.sin() * (x / LOBES).sin() / (x * x)
+  } else {
+    0_f32
+  }
+}
+
+#[allow(
+  clippy::cast_precision_loss,
+  clippy::cast_possible_truncation,
+  clippy::cast_sign_loss
+)]
+fn contributions(src: usize, dst: usize, filter: Filter) -> Vec<Contribution> {
+  let scale = src as f32 / dst as f32;
+  let filter_scale = scale.max(1_f32);
+  let support = filter.support() * filter_scale;
+  (0..dst)
+    .map(|i| {
+      let center = (i as f32 + 0.5) * scale;
+      if let Filter::Nearest = filter {
+        return Contribution {
+          start: (center as usize).min(src - 1),
+          weights: vec![1_f32],
+        };
+      }
+      let start = (center - support).floor().max(0_f32) as usize;
+      let end = ((center + support).ceil() as usize).min(src);
+      let mut weights = (start..end)
+        .map(|j| filter.weight((j as f32 + 0.5 - center) / filter_scale))
+        .collect::<Vec<_>>();
+      let sum = weights.iter().sum::<f32>();
+      if sum != 0_f32 {
+        weights.iter_mut().for_each(|w| *w /= sum);
+      }
+      Contribution { start, weights }
+    })
+    .collect()
+}
+
+/// Separable resampler for RGBA images of a fixed size
+pub(crate) struct Resizer {
+  src_width: usize,
+  dst_width: usize,
+  horizontal: Vec<Contribution>,
+  vertical: Vec<Contribution>,
+  tmp: Vec<f32>,
+}
+
+impl Resizer {
+  /// Resamples with the lanczos filter
+  pub(crate) fn new(src: (usize, usize), dst: (usize, usize)) -> Self {
+    Self::with_filter(src, dst, Filter::Lanczos)
+  }
+
+  pub(crate) fn with_filter(src: (usize, usize), dst: (usize, usize), filter: Filter) -> Self {
+    Self {
+      src_width: src.0,
+      dst_width: dst.0,
+      horizontal: contributions(src.0, dst.0, filter),
+      vertical: contributions(src.1, dst.1, filter),
+      tmp: vec![0_f32; dst.0 * src.1 * 4],
+    }
+  }
+
+  pub(crate) fn resize(&mut self, src: &[u8], dst: &mut Vec<u8>) {
+    let rows = self.tmp.len() / (self.dst_width * 4).max(1);
+    for y in 0..rows {
+      let row = &src[y * self.src_width * 4..];
+      for (x, c) in self.horizontal.iter().enumerate() {
+        let mut px = [0_f32; 4];
+        for (j, w) in c.weights.iter().enumerate() {
+          let s = &row[(c.start + j) * 4..][..4];
+          for k in 0..4 {
+            px[k] += f32::from(s[k]) * w;
+          }
+        }
+        self.tmp[(y * self.dst_width + x) * 4..][..4].copy_from_slice(&px);
+      }
+    }
+
+    dst.clear();
+    for c in &self.vertical {
+      for x in 0..self.dst_width {
+        let mut px = [0_f32; 4];
+        for (j, w) in c.weights.iter().enumerate() {
+          let s = &self.tmp[((c.start + j) * self.dst_width + x) * 4..][..4];
+          for k in 0..4 {
+            px[k] += s[k] * w;
+          }
+        }
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        dst.extend(px.map(|v| v.round().clamp(0_f32, 255_f32) as u8));
+      }
+    }
+  }
+}
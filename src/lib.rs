@@ -1,6 +1,7 @@
 use std::borrow::Cow;
 
 mod components;
+mod gif;
 
 #[cfg(feature = "node")]
 mod napi;
@@ -4,7 +4,7 @@ use std::{
   borrow::Cow,
   ffi::{c_char, c_int, c_uint, c_void, CStr, CString},
   ptr::{self, null_mut},
-  slice::from_raw_parts,
+  slice::{from_raw_parts, from_raw_parts_mut},
 };
 
 use crate::Result;
@@ -14,6 +14,7 @@ pub(crate) type deferred = *mut c_void;
 pub(crate) type value = *mut c_void;
 pub(crate) type callback_info = *mut c_void;
 pub(crate) type async_work = *mut c_void;
+pub(crate) type threadsafe_function = *mut c_void;
 pub(crate) type nref = value;
 
 pub(crate) type addon_register_func = extern "C" fn(env: env, exports: value) -> value;
@@ -24,6 +25,10 @@ pub(crate) type async_complete_callback =
 
 pub(crate) type finalize =
   extern "C" fn(env: env, finalize_data: *mut c_void, finalize_hint: *mut c_void);
+/// `env` is null while the environment is torn down, in which case only
+/// `context` is to be released
+pub(crate) type threadsafe_function_call_js =
+  extern "C" fn(env: env, js_callback: value, context: *mut c_void, data: *mut c_void);
 
 #[allow(dead_code)]
 #[repr(C)]
@@ -128,6 +133,35 @@ pub(crate) enum valuetype {
   Bigint,
 }
 
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy)]
+#[allow(dead_code)]
+pub(crate) enum typedarray_type {
+  Int8,
+  #[default]
+  Uint8,
+  Uint8Clamped,
+  Int16,
+  Uint16,
+  Int32,
+  Uint32,
+  Float32,
+  Float64,
+  Bigint64,
+  Biguint64,
+}
+
+impl typedarray_type {
+  fn bytes_per_element(self) -> usize {
+    match self {
+      Self::Int8 | Self::Uint8 | Self::Uint8Clamped => 1,
+      Self::Int16 | Self::Uint16 => 2,
+      Self::Int32 | Self::Uint32 | Self::Float32 => 4,
+      Self::Float64 | Self::Bigint64 | Self::Biguint64 => 8,
+    }
+  }
+}
+
 #[cfg_attr(target_os = "windows", link(name = "lib\\node"))]
 extern "C" {
   fn napi_module_register(module: *mut module);
@@ -160,6 +194,25 @@ extern "C" {
   ) -> status;
   fn napi_queue_async_work(env: env, work: async_work) -> status;
   fn napi_delete_async_work(env: env, work: async_work) -> status;
+  fn napi_create_threadsafe_function(
+    env: env,
+    func: value,
+    async_resource: value,
+    async_resource_name: value,
+    max_queue_size: usize,
+    initial_thread_count: usize,
+    thread_finalize_data: *mut c_void,
+    thread_finalize_cb: Option<finalize>,
+    context: *mut c_void,
+    call_js_cb: threadsafe_function_call_js,
+    result: *mut threadsafe_function,
+  ) -> status;
+  fn napi_call_threadsafe_function(
+    func: threadsafe_function,
+    data: *mut c_void,
+    is_blocking: c_int,
+  ) -> status;
+  fn napi_release_threadsafe_function(func: threadsafe_function, mode: c_int) -> status;
   fn napi_create_string_utf8(
     env: env,
     str: *const c_char,
@@ -175,6 +228,7 @@ extern "C" {
     data: *mut *mut c_void,
   ) -> status;
   fn napi_get_value_uint32(env: env, value: value, result: *mut u32) -> status;
+  fn napi_get_value_bool(env: env, value: value, result: *mut bool) -> status;
   fn napi_create_uint32(env: env, value: u32, result: *mut value) -> status;
   fn napi_get_value_string_utf8(
     env: env,
@@ -197,6 +251,14 @@ extern "C" {
     result_data: *mut *mut c_void,
     result: *mut value,
   ) -> status;
+  fn napi_create_external_buffer(
+    env: env,
+    length: usize,
+    data: *mut c_void,
+    finalize_cb: finalize,
+    finalize_hint: *mut c_void,
+    result: *mut value,
+  ) -> status;
   fn napi_get_property(env: env, object: value, key: value, result: *mut value) -> status;
   fn napi_set_property(env: env, object: value, key: value, value: value) -> status;
   fn napi_define_class(
@@ -224,6 +286,7 @@ extern "C" {
     initial_refcount: u32,
     result: *mut nref,
   ) -> status;
+  fn napi_delete_reference(env: env, nref: nref) -> status;
   fn napi_get_global(env: env, result: *mut value) -> status;
   fn napi_get_named_property(
     env: env,
@@ -238,6 +301,7 @@ extern "C" {
     value: value,
   ) -> status;
   fn napi_create_object(env: env, result: *mut value) -> status;
+  fn napi_create_array_with_length(env: env, length: usize, result: *mut value) -> status;
   fn napi_new_instance(
     env: env,
     cons: value,
@@ -247,14 +311,15 @@ extern "C" {
   ) -> status;
   fn napi_get_reference_value(env: env, nref: nref, result: *mut value) -> status;
   fn napi_get_value_double(env: env, value: value, result: *mut f64) -> status;
-  // fn napi_call_function(
-  //   env: env,
-  //   recv: value,
-  //   func: value,
-  //   argc: usize,
-  //   argv: *const value,
-  //   result: *mut value,
-  // ) -> status;
+  fn napi_call_function(
+    env: env,
+    recv: value,
+    func: value,
+    argc: usize,
+    argv: *const value,
+    result: *mut value,
+  ) -> status;
+  fn napi_get_and_clear_last_exception(env: env, result: *mut value) -> status;
   fn napi_typeof(env: env, value: value, result: *mut valuetype) -> status;
   fn napi_is_array(env: env, value: value, result: *mut bool) -> status;
   fn napi_get_array_length(env: env, value: value, result: *mut u32) -> status;
@@ -265,6 +330,25 @@ extern "C" {
     data: *mut *mut c_void,
     length: *mut usize,
   ) -> status;
+  fn napi_is_typedarray(env: env, value: value, result: *mut bool) -> status;
+  fn napi_get_typedarray_info(
+    env: env,
+    typedarray: value,
+    r#type: *mut typedarray_type,
+    length: *mut usize,
+    data: *mut *mut c_void,
+    arraybuffer: *mut value,
+    byte_offset: *mut usize,
+  ) -> status;
+  fn napi_is_dataview(env: env, value: value, result: *mut bool) -> status;
+  fn napi_get_dataview_info(
+    env: env,
+    dataview: value,
+    bytelength: *mut usize,
+    data: *mut *mut c_void,
+    arraybuffer: *mut value,
+    byte_offset: *mut usize,
+  ) -> status;
 }
 
 macro_rules! unwrap_throw {
@@ -361,9 +445,75 @@ pub(crate) fn get_buffer_info(env: env, v: value) -> Result<&'static [u8]> {
     &unsafe { napi_get_buffer_info(env, v, &mut data, &mut len) },
     (),
   )?;
+  // Empty buffers may come without any data pointer
+  if data.is_null() {
+    return Ok(&[]);
+  }
   Ok(unsafe { from_raw_parts(data.cast(), len) })
 }
 
+pub(crate) fn get_buffer_info_mut(env: env, v: value) -> Result<&'static mut [u8]> {
+  let mut data: *mut c_void = null_mut();
+  let mut len = 0_usize;
+  call(
+    env,
+    &unsafe { napi_get_buffer_info(env, v, &mut data, &mut len) },
+    (),
+  )?;
+  // Empty buffers may come without any data pointer
+  if data.is_null() {
+    return Ok(&mut []);
+  }
+  Ok(unsafe { from_raw_parts_mut(data.cast(), len) })
+}
+
+/// The bytes viewed by a `TypedArray`, `Buffer` included, or a `DataView`.
+/// `None` for any other value
+pub(crate) fn get_view_info(env: env, v: value) -> Result<Option<&'static [u8]>> {
+  let mut data: *mut c_void = null_mut();
+  let mut len = 0_usize;
+  let mut is_view = false;
+  call(
+    env,
+    &unsafe { napi_is_typedarray(env, v, &mut is_view) },
+    (),
+  )?;
+  if is_view {
+    let mut r#type = typedarray_type::default();
+    call(
+      env,
+      &unsafe {
+        napi_get_typedarray_info(
+          env,
+          v,
+          &mut r#type,
+          &mut len,
+          &mut data,
+          null_mut(),
+          null_mut(),
+        )
+      },
+      (),
+    )?;
+    len *= r#type.bytes_per_element();
+  } else {
+    call(env, &unsafe { napi_is_dataview(env, v, &mut is_view) }, ())?;
+    if !is_view {
+      return Ok(None);
+    }
+    call(
+      env,
+      &unsafe { napi_get_dataview_info(env, v, &mut len, &mut data, null_mut(), null_mut()) },
+      (),
+    )?;
+  }
+  // Empty views may come without any data pointer
+  if data.is_null() {
+    return Ok(Some(&[]));
+  }
+  Ok(Some(unsafe { from_raw_parts(data.cast(), len) }))
+}
+
 pub(crate) fn is_buffer(env: env, v: value) -> Result<bool> {
   let mut result = false;
   call(env, &unsafe { napi_is_buffer(env, v, &mut result) }, result)
@@ -457,6 +607,15 @@ pub(crate) fn create_object(env: env) -> Result<value> {
   )
 }
 
+pub(crate) fn create_array(env: env, len: usize) -> Result<value> {
+  let mut result: value = null_mut();
+  call(
+    env,
+    &unsafe { napi_create_array_with_length(env, len, &mut result) },
+    result,
+  )
+}
+
 pub(crate) fn set_property(
   env: env,
   object: value,
@@ -493,6 +652,10 @@ pub(crate) fn create_reference(env: env, value: value) -> Result<nref> {
   )
 }
 
+pub(crate) fn delete_reference(env: env, nref: nref) -> Result<()> {
+  call(env, &unsafe { napi_delete_reference(env, nref) }, ())
+}
+
 pub(crate) fn wrap(
   env: env,
   js_object: value,
@@ -561,6 +724,15 @@ pub(crate) fn get_value_uint32(env: env, v: value) -> Result<u32> {
   )
 }
 
+pub(crate) fn get_value_bool(env: env, v: value) -> Result<bool> {
+  let mut result = false;
+  call(
+    env,
+    &unsafe { napi_get_value_bool(env, v, &mut result) },
+    result,
+  )
+}
+
 pub(crate) fn create_uint32(env: env, v: u32) -> Result<value> {
   let mut result: value = null_mut();
   call(
@@ -662,6 +834,8 @@ pub(crate) fn call<T>(env: env, status: &status, value: T) -> Result<T> {
   Ok(value)
 }
 
+// Only called by `REGISTER`, which the test harness leaves out
+#[cfg_attr(test, allow(dead_code))]
 pub(crate) fn module_register(module: &mut module) {
   unsafe { napi_module_register(module) }
 }
@@ -692,6 +866,32 @@ pub(crate) fn create_buffer_copy(env: env, buffer: &[u8]) -> Result<value> {
   )
 }
 
+/// Creates a `Buffer` over `buffer` without copying it. `finalize_cb` is
+/// called once the `Buffer` is collected, until which `buffer` must stay
+/// allocated
+pub(crate) fn create_external_buffer(
+  env: env,
+  buffer: &mut [u8],
+  finalize_cb: finalize,
+  finalize_hint: *mut c_void,
+) -> Result<value> {
+  let mut result: value = null_mut();
+  call(
+    env,
+    &unsafe {
+      napi_create_external_buffer(
+        env,
+        buffer.len(),
+        buffer.as_mut_ptr().cast(),
+        finalize_cb,
+        finalize_hint,
+        &mut result,
+      )
+    },
+    result,
+  )
+}
+
 pub(crate) fn create_function(
   env: env,
   name: &str,
@@ -706,6 +906,24 @@ pub(crate) fn create_function(
   )
 }
 
+/// Calls `func` on `recv`. A thrown exception is cleared and returned as the
+/// error
+pub(crate) fn call_function(env: env, recv: value, func: value, args: &[value]) -> Result<value> {
+  let mut result: value = null_mut();
+  let status =
+    unsafe { napi_call_function(env, recv, func, args.len(), args.as_ptr(), &mut result) };
+  if is_exception_pending(env) {
+    let mut error: value = null_mut();
+    unsafe { napi_get_and_clear_last_exception(env, &mut error) };
+    return Err(Cow::Owned(
+      get_named_property(env, error, "message")
+        .and_then(|v| get_value_string(env, v))
+        .unwrap_or_else(|_| "The function call threw".to_owned()),
+    ));
+  }
+  call(env, &status, result)
+}
+
 pub(crate) fn create_double(env: env, value: f64) -> Result<value> {
   let mut result: value = null_mut();
   call(
@@ -829,3 +1047,52 @@ pub(crate) fn queue_async_work(env: env, work: async_work) -> Result<()> {
 pub(crate) fn delete_async_work(env: env, work: async_work) -> Result<()> {
   call(env, &unsafe { napi_delete_async_work(env, work) }, ())
 }
+
+/// Schedules `call_js` with `context` on the main thread once dropped, from
+/// whichever thread drops it. Unlike an async_work it does not hold a libuv
+/// pool thread while waiting
+pub(crate) struct Notifier(threadsafe_function);
+
+unsafe impl Send for Notifier {}
+
+impl Notifier {
+  pub(crate) fn new(
+    env: env,
+    resource: &str,
+    context: *mut c_void,
+    call_js: threadsafe_function_call_js,
+  ) -> Result<Self> {
+    let resource = create_string_utf8(env, resource)?;
+    let mut result: threadsafe_function = null_mut();
+    call(
+      env,
+      &unsafe {
+        napi_create_threadsafe_function(
+          env,
+          null_mut(),
+          null_mut(),
+          resource,
+          0,
+          1,
+          null_mut(),
+          None,
+          context,
+          call_js,
+          &mut result,
+        )
+      },
+      Self(result),
+    )
+  }
+}
+
+impl Drop for Notifier {
+  fn drop(&mut self) {
+    // Non-blocking calls on an unbounded queue only fail while the
+    // environment is torn down, at which point the context is leaked
+    unsafe {
+      napi_call_threadsafe_function(self.0, null_mut(), 0);
+      napi_release_threadsafe_function(self.0, 0);
+    }
+  }
+}